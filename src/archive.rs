@@ -0,0 +1,331 @@
+use crate::backend::Backend;
+use flate2::read::DeflateDecoder;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::Read;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+
+/// The end of central directory record is 22 bytes followed by a comment
+/// of up to 64 KB, so it always lies within this many bytes of the end.
+const MAX_TAIL: u64 = 22 + 65_535;
+
+/// Central directories larger than this are not downloaded.
+const MAX_CENTRAL_DIRECTORY: u64 = 64 * 1_048_576;
+
+/// Entries larger than this, compressed or not, are not read.
+const MAX_ENTRY: u64 = 64 * 1_048_576;
+
+/// A file stored in a ZIP archive, from the archive's central directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    /// Path inside the archive.
+    pub name: String,
+    /// Uncompressed size.
+    pub size: u64,
+    pub compressed_size: u64,
+    pub modified: Option<String>,
+    pub encrypted: bool,
+    #[serde(skip)]
+    method: u16,
+    #[serde(skip)]
+    header_offset: u64,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Formats an MS-DOS date and time as `YYYY-MM-DD HH:MM:SS`.
+fn format_dos_time(date: u16, time: u16) -> Option<String> {
+    if date == 0 {
+        return None;
+    }
+    Some(format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        (date >> 9) + 1980,
+        (date >> 5) & 0x0F,
+        date & 0x1F,
+        time >> 11,
+        (time >> 5) & 0x3F,
+        (time & 0x1F) * 2
+    ))
+}
+
+/// Bytes fetched from the end of an archive, kept so that records lying in
+/// them need no further request.
+struct Tail {
+    start: u64,
+    data: Vec<u8>,
+}
+
+impl Tail {
+    fn read(
+        &self,
+        url: &str,
+        offset: u64,
+        length: u64,
+        backend: &dyn Backend,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        if let Some(start) = offset.checked_sub(self.start)
+            && let Some(end) = start.checked_add(length)
+            && let Some(bytes) = self.data.get(start as usize..end as usize)
+        {
+            return Ok(bytes.to_vec());
+        }
+        let data = backend.fetch_range(url, offset, length as usize)?;
+        if (data.len() as u64) < length {
+            return Err(format!("Archive {} ends early", url).into());
+        }
+        Ok(data)
+    }
+}
+
+/// Parses one central directory header at `pos`, returning the entry and
+/// the position of the next header.
+fn parse_entry(data: &[u8], pos: usize) -> Option<(ArchiveEntry, usize)> {
+    let flags = u16_at(data, pos + 8)?;
+    let name_length = u16_at(data, pos + 28)? as usize;
+    let extra_length = u16_at(data, pos + 30)? as usize;
+    let comment_length = u16_at(data, pos + 32)? as usize;
+    let name_start = pos + 46;
+    let extra_start = name_start + name_length;
+    let name = data.get(name_start..extra_start)?;
+    let extra = data.get(extra_start..extra_start + extra_length)?;
+
+    let mut size = u32_at(data, pos + 24)? as u64;
+    let mut compressed_size = u32_at(data, pos + 20)? as u64;
+    let mut header_offset = u32_at(data, pos + 42)? as u64;
+    // ZIP64 extended information holds, in this order, whichever of the
+    // three values overflowed their 32-bit fields.
+    let mut field = 0;
+    while field + 4 <= extra.len() {
+        let id = u16_at(extra, field)?;
+        let length = u16_at(extra, field + 2)? as usize;
+        if id == 0x0001 {
+            let mut value = field + 4;
+            for target in [&mut size, &mut compressed_size, &mut header_offset] {
+                if *target == u32::MAX as u64 {
+                    *target = u64_at(extra, value)?;
+                    value += 8;
+                }
+            }
+        }
+        field += 4 + length;
+    }
+
+    let entry = ArchiveEntry {
+        name: String::from_utf8_lossy(name).into_owned(),
+        size,
+        compressed_size,
+        modified: format_dos_time(u16_at(data, pos + 14)?, u16_at(data, pos + 12)?),
+        encrypted: flags & 1 != 0,
+        method: u16_at(data, pos + 10)?,
+        header_offset,
+    };
+    Some((entry, extra_start + extra_length + comment_length))
+}
+
+/// Lists the files in the ZIP archive at `url` by reading only its central
+/// directory: the end of the file is fetched to locate it, then the
+/// directory itself unless the tail already covers it.
+pub fn list_zip(url: &str, backend: &dyn Backend) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    let (start, data) = backend.fetch_tail(url, MAX_TAIL)?;
+    let tail = Tail { start, data };
+    let end = (0..tail.data.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(&tail.data, i) == Some(END_SIGNATURE))
+        .ok_or("no end of central directory record")?;
+
+    let truncated = "truncated end of central directory record";
+    let mut count = u16_at(&tail.data, end + 10).ok_or(truncated)? as u64;
+    let mut directory_size = u32_at(&tail.data, end + 12).ok_or(truncated)? as u64;
+    let mut directory_offset = u32_at(&tail.data, end + 16).ok_or(truncated)? as u64;
+    if count == u16::MAX as u64
+        || directory_size == u32::MAX as u64
+        || directory_offset == u32::MAX as u64
+    {
+        let locator = end
+            .checked_sub(20)
+            .filter(|&i| u32_at(&tail.data, i) == Some(ZIP64_LOCATOR_SIGNATURE))
+            .ok_or("missing ZIP64 end of central directory locator")?;
+        let record_offset = u64_at(&tail.data, locator + 8).ok_or(truncated)?;
+        let record = tail.read(url, record_offset, 56, backend)?;
+        if u32_at(&record, 0) != Some(ZIP64_END_SIGNATURE) {
+            return Err("invalid ZIP64 end of central directory record".into());
+        }
+        count = u64_at(&record, 32).ok_or(truncated)?;
+        directory_size = u64_at(&record, 40).ok_or(truncated)?;
+        directory_offset = u64_at(&record, 48).ok_or(truncated)?;
+    }
+    if directory_size > MAX_CENTRAL_DIRECTORY {
+        return Err(format!("central directory of {} bytes is too large", directory_size).into());
+    }
+
+    let directory = tail.read(url, directory_offset, directory_size, backend)?;
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    for _ in 0..count {
+        if u32_at(&directory, pos) != Some(CENTRAL_HEADER_SIGNATURE) {
+            break;
+        }
+        let (entry, next) = parse_entry(&directory, pos).ok_or("truncated central directory")?;
+        pos = next;
+        // Folders are stored as empty entries ending in `/`.
+        if !entry.name.ends_with('/') {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Downloads and decompresses a single entry of the archive at `url`.
+pub fn read_entry(
+    url: &str,
+    entry: &ArchiveEntry,
+    backend: &dyn Backend,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if entry.encrypted {
+        return Err(format!("{} is encrypted", entry.name).into());
+    }
+    if entry.size > MAX_ENTRY || entry.compressed_size > MAX_ENTRY {
+        return Err(format!("{} is too large", entry.name).into());
+    }
+    let header = backend.fetch_range(url, entry.header_offset, 30)?;
+    if u32_at(&header, 0) != Some(LOCAL_HEADER_SIGNATURE) {
+        return Err(format!("no local header for {}", entry.name).into());
+    }
+    let name_length = u16_at(&header, 26).unwrap_or(0) as u64;
+    let extra_length = u16_at(&header, 28).unwrap_or(0) as u64;
+    let data_start = entry
+        .header_offset
+        .checked_add(30 + name_length + extra_length)
+        .ok_or("invalid local header offset")?;
+    let data = backend.fetch_range(url, data_start, entry.compressed_size as usize)?;
+
+    match entry.method {
+        0 => Ok(data),
+        8 => {
+            let mut contents = Vec::new();
+            DeflateDecoder::new(data.as_slice())
+                .take(entry.size)
+                .read_to_end(&mut contents)?;
+            Ok(contents)
+        }
+        method => Err(format!("unsupported compression method {}", method).into()),
+    }
+}
+
+/// URL under which an entry is reported, e.g. `.../photos.zip#a/b.jpg`.
+pub fn entry_url(archive_url: &str, name: &str) -> String {
+    format!("{}#{}", archive_url, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryBackend;
+    use flate2::Compression;
+    use flate2::write::DeflateEncoder;
+    use std::io::Write;
+
+    /// Builds an archive with one entry, declaring `size` as its
+    /// uncompressed size.
+    fn zip(name: &str, method: u16, data: &[u8], size: u32) -> Vec<u8> {
+        let fields = |signature: u32| {
+            [
+                &signature.to_le_bytes()[..],
+                &[20, 0],
+                &0u16.to_le_bytes(),
+                &method.to_le_bytes(),
+                &[0; 8],
+                &(data.len() as u32).to_le_bytes(),
+                &size.to_le_bytes(),
+                &(name.len() as u16).to_le_bytes(),
+                &[0, 0],
+            ]
+            .concat()
+        };
+        let local = [fields(LOCAL_HEADER_SIGNATURE), name.as_bytes().to_vec()].concat();
+        let central = [
+            &CENTRAL_HEADER_SIGNATURE.to_le_bytes()[..],
+            &[20, 0],
+            &fields(0)[4..],
+            &[0; 14],
+            name.as_bytes(),
+        ]
+        .concat();
+        let directory_offset = (local.len() + data.len()) as u32;
+        let end = [
+            &END_SIGNATURE.to_le_bytes()[..],
+            &[0, 0, 0, 0, 1, 0, 1, 0],
+            &(central.len() as u32).to_le_bytes(),
+            &directory_offset.to_le_bytes(),
+            &[0, 0],
+        ]
+        .concat();
+        [local, data.to_vec(), central, end].concat()
+    }
+
+    #[test]
+    fn lists_and_reads_stored_entry() {
+        let backend = MemoryBackend(zip("a/b.txt", 0, b"hello", 5));
+        let entries = list_zip("memory:///a.zip", &backend).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "a/b.txt");
+        assert_eq!(entries[0].size, 5);
+        let data = read_entry("memory:///a.zip", &entries[0], &backend).unwrap();
+        assert_eq!(data, b"hello");
+    }
+
+    #[test]
+    fn inflates_no_more_than_declared_size() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&[0; 100_000]).unwrap();
+        let backend = MemoryBackend(zip("bomb.bin", 8, &encoder.finish().unwrap(), 10));
+        let entries = list_zip("memory:///a.zip", &backend).unwrap();
+        let data = read_entry("memory:///a.zip", &entries[0], &backend).unwrap();
+        assert_eq!(data.len(), 10);
+    }
+
+    #[test]
+    fn rejects_entry_over_limit() {
+        let backend = MemoryBackend(zip("large.bin", 8, b"", u32::MAX - 1));
+        let entries = list_zip("memory:///a.zip", &backend).unwrap();
+        assert!(read_entry("memory:///a.zip", &entries[0], &backend).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_end_record() {
+        let backend = MemoryBackend(b"PK\x05\x06\0\0\0\0\x01\0\x01\0\xFF\xFF\xFF\xFF".to_vec());
+        assert!(list_zip("memory:///a.zip", &backend).is_err());
+    }
+
+    #[test]
+    fn rejects_directory_beyond_end() {
+        let mut data = zip("a.txt", 0, b"a", 1);
+        let end = data.len() - 22;
+        data[end + 16..end + 20].copy_from_slice(&u32::MAX.to_le_bytes()[..]);
+        data[end + 12..end + 16].copy_from_slice(&1000u32.to_le_bytes());
+        let backend = MemoryBackend(data);
+        assert!(list_zip("memory:///a.zip", &backend).is_err());
+    }
+}
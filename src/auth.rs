@@ -0,0 +1,457 @@
+use crate::urls;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use md5::{Digest, Md5};
+use reqwest::blocking::{Request, Response};
+use reqwest::header::{
+    AUTHORIZATION, COOKIE, HeaderMap, HeaderName, HeaderValue, WWW_AUTHENTICATE,
+};
+use sha2::Sha256;
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fs;
+use std::hash::BuildHasher;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use toml::Table;
+use url::Url;
+
+/// Authentication options as written in the `[auth]` config table or on
+/// the command line.
+#[derive(Default)]
+pub struct AuthSettings {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub digest: bool,
+    pub bearer_token: Option<String>,
+    pub cookie_jar: Option<String>,
+    /// Extra headers in `Name: value` form.
+    pub headers: Vec<String>,
+    /// The host, with a non-default port, that credentials and headers are
+    /// sent to. Every host gets them if unset.
+    pub host: Option<String>,
+}
+
+fn string_value(table: &Table, key: &str) -> Option<String> {
+    table
+        .get(key)
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+impl AuthSettings {
+    pub fn from_config(config: &Table) -> Self {
+        let Some(auth) = config.get("auth").and_then(|v| v.as_table()) else {
+            return AuthSettings::default();
+        };
+
+        let headers = auth
+            .get("headers")
+            .and_then(|v| v.as_table())
+            .map(|headers| {
+                headers
+                    .iter()
+                    .filter_map(|(name, value)| Some(format!("{}: {}", name, value.as_str()?)))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        AuthSettings {
+            username: string_value(auth, "username"),
+            password: string_value(auth, "password"),
+            digest: string_value(auth, "method").as_deref() == Some("digest"),
+            bearer_token: string_value(auth, "bearer_token"),
+            cookie_jar: string_value(auth, "cookie_jar"),
+            headers,
+            host: None,
+        }
+    }
+}
+
+enum Credentials {
+    None,
+    Basic { username: String, password: String },
+    Digest { username: String, password: String },
+    Bearer(String),
+}
+
+/// One cookie from a Netscape `cookies.txt` file.
+struct Cookie {
+    domain: String,
+    include_subdomains: bool,
+    path: String,
+    secure: bool,
+    /// Unix timestamp; 0 for session cookies.
+    expires: u64,
+    name: String,
+    value: String,
+}
+
+impl Cookie {
+    fn matches(&self, request: &Request, now: u64) -> bool {
+        let url = request.url();
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+        let domain_matches = host == self.domain
+            || (self.include_subdomains && host.ends_with(&format!(".{}", self.domain)));
+
+        domain_matches
+            && url.path().starts_with(&self.path)
+            && (!self.secure || url.scheme() == "https")
+            && (self.expires == 0 || self.expires > now)
+    }
+}
+
+/// Parses the tab-separated Netscape cookie file format written by curl,
+/// wget and browser export extensions.
+fn load_cookie_jar(path: &str) -> Result<Vec<Cookie>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read cookie jar {}: {}", path, e))?;
+
+    let mut cookies = Vec::new();
+    for line in contents.lines() {
+        // curl marks HttpOnly cookies with a prefix on an otherwise
+        // commented-out line.
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 7 {
+            continue;
+        }
+        cookies.push(Cookie {
+            domain: fields[0].trim_start_matches('.').to_ascii_lowercase(),
+            include_subdomains: fields[1].eq_ignore_ascii_case("TRUE"),
+            path: fields[2].to_string(),
+            secure: fields[3].eq_ignore_ascii_case("TRUE"),
+            expires: fields[4].parse().unwrap_or(0),
+            name: fields[5].to_string(),
+            value: fields[6].to_string(),
+        });
+    }
+    Ok(cookies)
+}
+
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), Box<dyn Error>> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid header (expected `Name: value`): {}", header))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())?;
+    let mut value = HeaderValue::from_str(value.trim())?;
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// A `WWW-Authenticate: Digest` challenge from the server.
+struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: String,
+    qop_auth: bool,
+    /// Requests sent with the current nonce.
+    nonce_count: u32,
+}
+
+/// Splits `key=value, key="quoted, value"` challenge parameters.
+fn challenge_params(params: &str) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_ascii_lowercase();
+        let after = after.trim_start();
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (after[..end].trim(), &after[end..])
+            }
+        };
+        result.push((key, value.to_string()));
+        rest = remaining.trim_start_matches([',', ' ']);
+    }
+    result
+}
+
+impl DigestChallenge {
+    fn parse(header: &str) -> Option<Self> {
+        let (scheme, params) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("digest") {
+            return None;
+        }
+
+        let mut realm = None;
+        let mut nonce = None;
+        let mut opaque = None;
+        let mut algorithm = "MD5".to_string();
+        let mut qop_auth = false;
+        for (key, value) in challenge_params(params) {
+            match key.as_str() {
+                "realm" => realm = Some(value),
+                "nonce" => nonce = Some(value),
+                "opaque" => opaque = Some(value),
+                "algorithm" => algorithm = value.to_ascii_uppercase(),
+                "qop" => qop_auth = value.split(',').any(|q| q.trim() == "auth"),
+                _ => {}
+            }
+        }
+
+        Some(DigestChallenge {
+            realm: realm?,
+            nonce: nonce?,
+            opaque,
+            algorithm,
+            qop_auth,
+            nonce_count: 0,
+        })
+    }
+
+    fn hash(&self, data: &str) -> String {
+        if self.algorithm.starts_with("SHA-256") {
+            format!("{:x}", Sha256::digest(data.as_bytes()))
+        } else {
+            format!("{:x}", Md5::digest(data.as_bytes()))
+        }
+    }
+
+    /// Builds the `Authorization` value for one request (RFC 7616).
+    fn authorization(&mut self, username: &str, password: &str, method: &str, uri: &str) -> String {
+        self.nonce_count += 1;
+        let nc = format!("{:08x}", self.nonce_count);
+        let cnonce = format!("{:016x}", RandomState::new().hash_one(SystemTime::now()));
+
+        let mut ha1 = self.hash(&format!("{}:{}:{}", username, self.realm, password));
+        if self.algorithm.ends_with("-SESS") {
+            ha1 = self.hash(&format!("{}:{}:{}", ha1, self.nonce, cnonce));
+        }
+        let ha2 = self.hash(&format!("{}:{}", method, uri));
+        let response = if self.qop_auth {
+            self.hash(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, self.nonce, nc, cnonce, ha2
+            ))
+        } else {
+            self.hash(&format!("{}:{}:{}", ha1, self.nonce, ha2))
+        };
+
+        let mut value = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{}\"",
+            username, self.realm, self.nonce, uri, self.algorithm, response
+        );
+        if self.qop_auth {
+            value.push_str(&format!(", qop=auth, nc={}, cnonce=\"{}\"", nc, cnonce));
+        }
+        if let Some(opaque) = &self.opaque {
+            value.push_str(&format!(", opaque=\"{}\"", opaque));
+        }
+        value
+    }
+}
+
+/// Credentials, cookies and extra headers added to requests. Cookies go
+/// wherever their domain matches; credentials and headers only to the
+/// scanned host. All values are marked sensitive so they never show up in
+/// debug output.
+pub struct Auth {
+    credentials: Credentials,
+    cookies: Vec<Cookie>,
+    headers: HeaderMap,
+    host: Option<String>,
+    challenge: Mutex<Option<DigestChallenge>>,
+}
+
+impl Auth {
+    pub fn new(settings: &AuthSettings) -> Result<Self, Box<dyn Error>> {
+        let credentials = match (&settings.bearer_token, &settings.username) {
+            (Some(token), _) => Credentials::Bearer(token.clone()),
+            (None, Some(username)) => {
+                let username = username.clone();
+                let password = settings.password.clone().unwrap_or_default();
+                if settings.digest {
+                    Credentials::Digest { username, password }
+                } else {
+                    Credentials::Basic { username, password }
+                }
+            }
+            (None, None) => Credentials::None,
+        };
+
+        let cookies = match &settings.cookie_jar {
+            Some(path) => load_cookie_jar(path)?,
+            None => Vec::new(),
+        };
+
+        let mut headers = HeaderMap::new();
+        for header in &settings.headers {
+            let (name, value) = parse_header(header)?;
+            headers.insert(name, value);
+        }
+
+        Ok(Auth {
+            credentials,
+            cookies,
+            headers,
+            host: settings.host.clone(),
+            challenge: Mutex::new(None),
+        })
+    }
+
+    /// Whether credentials and headers may be sent to `url`.
+    fn trusts(&self, url: &Url) -> bool {
+        self.host
+            .as_ref()
+            .is_none_or(|host| urls::host_key(url).as_ref() == Some(host))
+    }
+
+    /// Adds the matching cookies to `request`, and the configured headers
+    /// and credentials if it goes to the scanned host.
+    pub fn apply(&self, request: &mut Request) -> Result<(), Box<dyn Error>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let cookie = self
+            .cookies
+            .iter()
+            .filter(|c| c.matches(request, now))
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        let trusted = self.trusts(request.url());
+        let authorization = match &self.credentials {
+            _ if !trusted => None,
+            Credentials::None => None,
+            Credentials::Basic { username, password } => Some(format!(
+                "Basic {}",
+                BASE64.encode(format!("{}:{}", username, password))
+            )),
+            Credentials::Bearer(token) => Some(format!("Bearer {}", token)),
+            Credentials::Digest { username, password } => {
+                let uri = match request.url().query() {
+                    Some(query) => format!("{}?{}", request.url().path(), query),
+                    None => request.url().path().to_string(),
+                };
+                let method = request.method().as_str().to_string();
+                self.challenge
+                    .lock()
+                    .unwrap()
+                    .as_mut()
+                    .map(|challenge| challenge.authorization(username, password, &method, &uri))
+            }
+        };
+
+        let headers = request.headers_mut();
+        if trusted {
+            for (name, value) in &self.headers {
+                headers.insert(name, value.clone());
+            }
+        }
+        if !cookie.is_empty() {
+            let mut value = HeaderValue::from_str(&cookie)?;
+            value.set_sensitive(true);
+            headers.insert(COOKIE, value);
+        }
+        if let Some(authorization) = authorization {
+            let mut value = HeaderValue::from_str(&authorization)?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        Ok(())
+    }
+
+    /// Records the Digest challenge of a 401 response. Returns `true` if
+    /// the request should be sent again with fresh credentials.
+    pub fn accept_challenge(&self, response: &Response) -> bool {
+        if !matches!(self.credentials, Credentials::Digest { .. }) || !self.trusts(response.url()) {
+            return false;
+        }
+        let challenge = response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .find_map(DigestChallenge::parse);
+
+        match challenge {
+            Some(challenge) => {
+                *self.challenge.lock().unwrap() = Some(challenge);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Method;
+
+    fn request(url: &str) -> Request {
+        Request::new(Method::GET, Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn parses_digest_challenge() {
+        let challenge = DigestChallenge::parse(
+            r#"Digest realm="files, inc", qop="auth,auth-int", nonce="abc", opaque=xyz"#,
+        )
+        .unwrap();
+        assert_eq!(challenge.realm, "files, inc");
+        assert_eq!(challenge.nonce, "abc");
+        assert_eq!(challenge.opaque.as_deref(), Some("xyz"));
+        assert_eq!(challenge.algorithm, "MD5");
+        assert!(challenge.qop_auth);
+        assert!(DigestChallenge::parse(r#"Basic realm="files""#).is_none());
+        assert!(DigestChallenge::parse(r#"Digest realm="files""#).is_none());
+    }
+
+    #[test]
+    fn computes_digest_response() {
+        let mut challenge = DigestChallenge::parse(
+            r#"Digest realm="testrealm@host.com", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093""#,
+        )
+        .unwrap();
+        let value = challenge.authorization("Mufasa", "Circle Of Life", "GET", "/dir/index.html");
+        assert!(value.contains(r#"response="670fd8c2df070c60b045671b8b24ff02""#));
+        assert!(!value.contains("qop"));
+    }
+
+    #[test]
+    fn sends_credentials_only_to_scanned_host() {
+        let auth = Auth::new(&AuthSettings {
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            headers: vec!["X-Api-Key: 123".to_string()],
+            host: Some("files.example.com:8080".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut own = request("http://files.example.com:8080/pub/");
+        auth.apply(&mut own).unwrap();
+        assert!(own.headers().contains_key(AUTHORIZATION));
+        assert!(own.headers().contains_key("x-api-key"));
+
+        for url in [
+            "http://files.example.com/pub/",
+            "http://cdn.example.com:8080/pub/",
+        ] {
+            let mut other = request(url);
+            auth.apply(&mut other).unwrap();
+            assert!(other.headers().is_empty(), "{}", url);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use crate::http::HttpClient;
+use crate::jsonindex;
+use crate::listing::{self, ListingEntry};
+use crate::probe::{self, Probe};
+use crate::robots::Robots;
+use crate::urls;
+use crate::webdav;
+use reqwest::StatusCode;
+use reqwest::header::{ACCEPT, CONTENT_RANGE, CONTENT_TYPE, RANGE, SERVER};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+use std::sync::Mutex;
+use url::Url;
+
+/// A directory listing: the URL its entries' `href`s resolve against, and
+/// the entries in listing order.
+pub type RawListing = (String, Vec<ListingEntry>);
+
+/// The protocol-specific part of a scan. The crawler, filters and report
+/// are shared; a backend only knows how to list a directory and read a
+/// file on its kind of server.
+pub trait Backend: Sync {
+    fn list(&self, url: &str) -> Result<RawListing, Box<dyn Error>>;
+
+    /// Looks up the size and type of a file whose listing did not include
+    /// them.
+    fn probe(&self, url: &str) -> Result<Probe, Box<dyn Error>>;
+
+    /// Downloads a whole file, e.g. an image for the NSFW check.
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Downloads at most `length` bytes of a file, starting at `offset`;
+    /// none past its end.
+    fn fetch_range(&self, url: &str, offset: u64, length: usize)
+    -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Downloads at most the first `length` bytes of a file.
+    fn fetch_prefix(&self, url: &str, length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.fetch_range(url, 0, length)
+    }
+
+    /// Downloads at most the last `length` bytes of a file, returning them
+    /// with their offset. The size comes from the server, as the one in a
+    /// listing may be rounded.
+    fn fetch_tail(&self, url: &str, length: u64) -> Result<(u64, Vec<u8>), Box<dyn Error>> {
+        let size = self
+            .probe(url)?
+            .size
+            .ok_or_else(|| format!("Size of {} is unknown", url))?;
+        let start = size.saturating_sub(length);
+        Ok((
+            start,
+            self.fetch_range(url, start, (size - start) as usize)?,
+        ))
+    }
+
+    /// Whether the crawler may request `url` at all.
+    fn allows(&self, _url: &str) -> bool {
+        true
+    }
+}
+
+/// How the directories of one host are listed.
+#[derive(Clone)]
+enum ListingMode {
+    WebDav,
+    /// h5ai's JSON API at the given URL.
+    H5ai(String),
+    /// Index pages, as JSON where the server offers it and HTML otherwise.
+    Index,
+}
+
+/// Lists HTTP(S) directories. Servers that advertise WebDAV are asked
+/// with `PROPFIND`, h5ai servers through their API, and all others for
+/// their index page, preferring JSON over HTML.
+pub struct HttpBackend {
+    client: HttpClient,
+    robots: Robots,
+    /// The listing mode of each host, decided on its first listing.
+    modes: Mutex<HashMap<String, ListingMode>>,
+}
+
+impl HttpBackend {
+    pub fn new(client: HttpClient, respect_robots: bool) -> Self {
+        HttpBackend {
+            client,
+            robots: Robots::new(respect_robots),
+            modes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    fn mode(&self, host: &str, url: &str) -> ListingMode {
+        if let Some(mode) = self.modes.lock().unwrap().get(host) {
+            return mode.clone();
+        }
+        let mode = if webdav::supports_dav(url, &self.client) {
+            ListingMode::WebDav
+        } else {
+            ListingMode::Index
+        };
+        self.modes
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert(mode)
+            .clone()
+    }
+
+    fn list_webdav(&self, url: &str) -> Option<RawListing> {
+        match webdav::list(url, &self.client) {
+            Ok(Some(entries)) => {
+                println!(
+                    "Parsed {} entries from {} (WebDAV listing)",
+                    entries.len(),
+                    url
+                );
+                Some((urls::canonicalize(url, true), entries))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                eprintln!("PROPFIND failed for {}, falling back to HTML: {}", url, e);
+                None
+            }
+        }
+    }
+
+    fn list_h5ai(&self, api: &str, url: &str) -> Option<RawListing> {
+        match jsonindex::list_h5ai(api, url, &self.client) {
+            Ok(entries) => {
+                println!(
+                    "Parsed {} entries from {} (h5ai listing)",
+                    entries.len(),
+                    url
+                );
+                Some((urls::canonicalize(url, true), entries))
+            }
+            Err(e) => {
+                eprintln!("h5ai API failed for {}, falling back to HTML: {}", url, e);
+                None
+            }
+        }
+    }
+
+    /// Fetches one index page and parses its entries. The base URL is the
+    /// final URL after redirects, or the page's `<base href>`.
+    fn list_index(&self, host: &str, url: &str) -> Result<RawListing, Box<dyn Error>> {
+        let response = self
+            .client
+            .execute(|c| c.get(url).header(ACCEPT, jsonindex::ACCEPT))?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to fetch {}: {}", url, response.status()).into());
+        }
+
+        let page_url = response.url().to_string();
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let server = header(SERVER);
+        let content_type = header(CONTENT_TYPE);
+        let body = response.text()?;
+
+        if jsonindex::is_listing(content_type.as_deref(), &body) {
+            let (format, entries) = jsonindex::parse(&body)
+                .map_err(|e| format!("Failed to parse JSON listing of {}: {}", url, e))?;
+            println!(
+                "Parsed {} entries from {} ({} listing)",
+                entries.len(),
+                url,
+                format
+            );
+            return Ok((page_url, entries));
+        }
+
+        // An h5ai page only holds a fallback listing; switch the host over
+        // to the API.
+        if let Some(api) = jsonindex::h5ai_api(&page_url, &body) {
+            self.modes
+                .lock()
+                .unwrap()
+                .insert(host.to_string(), ListingMode::H5ai(api.clone()));
+            if let Some(listing) = self.list_h5ai(&api, url) {
+                return Ok(listing);
+            }
+        }
+
+        let listing = listing::parse_listing(server.as_deref(), &body);
+        println!(
+            "Parsed {} entries from {} ({} listing)",
+            listing.entries.len(),
+            url,
+            listing.parser
+        );
+
+        let base = listing
+            .base_href
+            .and_then(|href| urls::resolve(&page_url, &href))
+            .unwrap_or(page_url);
+        Ok((base, listing.entries))
+    }
+}
+
+impl Backend for HttpBackend {
+    fn list(&self, url: &str) -> Result<RawListing, Box<dyn Error>> {
+        let host = Url::parse(url)
+            .ok()
+            .as_ref()
+            .and_then(urls::host_key)
+            .unwrap_or_default();
+
+        let listing = match self.mode(&host, url) {
+            ListingMode::WebDav => self.list_webdav(url),
+            ListingMode::H5ai(api) => self.list_h5ai(&api, url),
+            ListingMode::Index => None,
+        };
+        match listing {
+            Some(listing) => Ok(listing),
+            None => self.list_index(&host, url),
+        }
+    }
+
+    fn probe(&self, url: &str) -> Result<Probe, Box<dyn Error>> {
+        probe::probe(url, &self.client)
+    }
+
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let response = self.client.get(url)?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to download {}: {}", url, response.status()).into());
+        }
+        Ok(response.bytes()?.to_vec())
+    }
+
+    /// Asks for a byte range. Servers that ignore it send the whole file,
+    /// which still works for a prefix but not for a range further in.
+    fn fetch_range(
+        &self,
+        url: &str,
+        offset: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let last = offset.saturating_add(length as u64).saturating_sub(1);
+        let range = format!("bytes={}-{}", offset, last);
+        let response = self
+            .client
+            .execute(|c| c.get(url).header(RANGE, range.as_str()))?;
+        // The range starts at or past the end of the file.
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            return Ok(Vec::new());
+        }
+        if !response.status().is_success() {
+            return Err(format!("Failed to download {}: {}", url, response.status()).into());
+        }
+        if offset > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(format!("{} does not support range requests", url).into());
+        }
+        let mut data = Vec::with_capacity(length);
+        response.take(length as u64).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Asks for a suffix range, which needs no size. The offset of the
+    /// tail comes from `Content-Range`.
+    fn fetch_tail(&self, url: &str, length: u64) -> Result<(u64, Vec<u8>), Box<dyn Error>> {
+        let range = format!("bytes=-{}", length);
+        let response = self
+            .client
+            .execute(|c| c.get(url).header(RANGE, range.as_str()))?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to download {}: {}", url, response.status()).into());
+        }
+        let start = match response.status() {
+            StatusCode::PARTIAL_CONTENT => response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("bytes "))
+                .and_then(|value| value.split_once('-'))
+                .and_then(|(start, _)| start.trim().parse().ok())
+                .ok_or_else(|| format!("Invalid Content-Range from {}", url))?,
+            // The whole file, which is fine if it is no longer than the tail.
+            _ if response.content_length().is_some_and(|size| size <= length) => 0,
+            _ => return Err(format!("{} does not support range requests", url).into()),
+        };
+        let mut data = Vec::new();
+        response.take(length).read_to_end(&mut data)?;
+        Ok((start, data))
+    }
+
+    fn allows(&self, url: &str) -> bool {
+        self.robots.allows(url, &self.client)
+    }
+}
+
+/// Serves one file from memory, for the tests of the format parsers.
+#[cfg(test)]
+pub struct MemoryBackend(pub Vec<u8>);
+
+#[cfg(test)]
+impl Backend for MemoryBackend {
+    fn list(&self, url: &str) -> Result<RawListing, Box<dyn Error>> {
+        Err(format!("{} is not a directory", url).into())
+    }
+
+    fn probe(&self, _url: &str) -> Result<Probe, Box<dyn Error>> {
+        Ok(Probe {
+            size: Some(self.0.len() as u64),
+            content_type: None,
+        })
+    }
+
+    fn fetch(&self, _url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(self.0.clone())
+    }
+
+    fn fetch_range(
+        &self,
+        _url: &str,
+        offset: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(self.0.len());
+        let end = start.saturating_add(length).min(self.0.len());
+        Ok(self.0[start..end].to_vec())
+    }
+}
@@ -0,0 +1,136 @@
+use crate::crawler::{DirectoryTask, Listings};
+use crate::filter::FilterRules;
+use crate::report::DirectoryReport;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+
+/// The options that decide what a scan finds, as given when it started.
+/// A checkpoint is only resumed with the same ones, since listings made
+/// under different ones would end up in one report.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanSettings {
+    pub max_depth: u32,
+    pub probe_sizes: bool,
+    pub sniff_types: bool,
+    pub list_archives: bool,
+    pub check_archive_images: bool,
+    pub media_metadata: bool,
+    pub document_metadata: bool,
+    pub allow_parent: bool,
+    pub allowed_hosts: Vec<String>,
+    pub filters: FilterRules,
+    pub respect_robots: bool,
+    /// Whether credentials, a token, cookies or headers were given. The
+    /// values themselves are not saved.
+    pub authenticated: bool,
+}
+
+/// Snapshot of an unfinished scan, written periodically so that a scan that
+/// dies can continue with `--resume` instead of starting over.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub root: String,
+    pub settings: ScanSettings,
+    /// Directories still to be listed, including those in flight when the
+    /// checkpoint was taken.
+    pub pending: Vec<DirectoryTask>,
+    /// Canonical URLs already claimed by completed listings.
+    pub visited: Vec<String>,
+    pub listings: Listings,
+    pub duplicates_suppressed: usize,
+    pub loops_suppressed: usize,
+    /// Report aggregated from the completed listings.
+    pub report: DirectoryReport,
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint to a temporary file first and renames it into
+    /// place, so a crash mid-write never leaves a truncated state file.
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let data = fs::read(path)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Fails unless the scan is resumed with the settings it started with,
+    /// naming the ones that differ.
+    pub fn check_settings(&self, settings: &ScanSettings) -> Result<(), Box<dyn Error>> {
+        let (serde_json::Value::Object(saved), serde_json::Value::Object(current)) = (
+            serde_json::to_value(&self.settings)?,
+            serde_json::to_value(settings)?,
+        ) else {
+            return Ok(());
+        };
+        let differing: Vec<&str> = saved
+            .iter()
+            .filter(|(key, value)| current.get(*key) != Some(value))
+            .map(|(key, _)| key.as_str())
+            .collect();
+        if differing.is_empty() {
+            return Ok(());
+        }
+        Err(format!(
+            "Checkpoint was written with different options ({}); pass the same options to resume",
+            differing.join(", ")
+        )
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(settings: ScanSettings) -> Checkpoint {
+        Checkpoint {
+            root: "http://example.com/".to_string(),
+            settings,
+            pending: Vec::new(),
+            visited: Vec::new(),
+            listings: Default::default(),
+            duplicates_suppressed: 0,
+            loops_suppressed: 0,
+            report: DirectoryReport::new(),
+        }
+    }
+
+    #[test]
+    fn resumes_with_same_settings() {
+        let settings = ScanSettings {
+            max_depth: 3,
+            allowed_hosts: vec!["mirror.example.com".to_string()],
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.json");
+        let path = path.to_str().unwrap();
+        checkpoint(settings.clone()).save(path).unwrap();
+        let loaded = Checkpoint::load(path).unwrap();
+        assert!(loaded.check_settings(&settings).is_ok());
+    }
+
+    #[test]
+    fn names_differing_settings() {
+        let saved = ScanSettings {
+            max_depth: 3,
+            ..Default::default()
+        };
+        let mut current = saved.clone();
+        current.max_depth = 5;
+        current.filters.exclude.push("*.iso".to_string());
+        let message = checkpoint(saved)
+            .check_settings(&current)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("max_depth"));
+        assert!(message.contains("filters"));
+        assert!(!message.contains("allow_parent"));
+    }
+}
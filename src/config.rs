@@ -0,0 +1,238 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use toml::Table;
+use toml_edit::{DocumentMut, Item};
+
+pub const CONFIG_PATH: &str = "resources/config.toml";
+pub const DEFAULT_CONFIG: &str = r#"# Open Directory Scanner Configuration
+
+# Model information
+[model]
+version = "0.0.0"  # Will be updated with the actual version from GitHub
+last_updated = ""  # Will be populated when model is downloaded
+url = "https://github.com/Fyko/nsfw/releases/latest/download/model.onnx"
+path = "resources/model.onnx"
+
+# NSFW detection thresholds
+[thresholds]
+porn = 0.5    # Porn classification threshold
+hentai = 0.6  # Hentai classification threshold
+sexy = 0.8    # Sexy classification threshold
+
+# Scanner defaults
+[scanner]
+default_depth = 3
+default_timeout = 30
+# Filters: a file is counted if it matches no exclude rule and, for each
+# kind of include rule given (globs, regexes, extensions), at least one rule
+# of that kind. Globs without a `/` match the file name, otherwise the URL
+# path. Directories are pruned by exclude rules and by path globs that
+# cannot match below them.
+include = []             # e.g. ["*.iso", "/pub/linux/**"]
+exclude = []
+include_regex = []
+exclude_regex = []
+include_extensions = []  # e.g. ["iso", "img"]
+exclude_extensions = []
+# min_size = "1M"        # Minimum file size (bytes or 1K/1M/1G)
+# max_size = "4G"        # Maximum file size
+
+# File types: each [file_types.<Category>] table maps a format to its
+# extensions. Compound extensions such as "tar.gz" take precedence over
+# "gz"; files with other extensions are counted as Other. Images are
+# checked for NSFW content, and .ogg/.webm files of the Audio and Video
+# categories are reclassified by the server's content type.
+[file_types.Image]
+JPEG = ["jpg", "jpeg"]
+PNG = ["png"]
+GIF = ["gif"]
+WebP = ["webp"]
+BMP = ["bmp"]
+SVG = ["svg"]
+TIFF = ["tiff", "tif"]
+HEIC = ["heic", "heif"]
+ICO = ["ico"]
+
+[file_types.Video]
+MP4 = ["mp4", "m4v"]
+WebM = ["webm"]
+AVI = ["avi"]
+QuickTime = ["mov"]
+Matroska = ["mkv"]
+Flash = ["flv"]
+"Windows Media" = ["wmv"]
+MPEG = ["mpg", "mpeg"]
+
+[file_types.Audio]
+MP3 = ["mp3"]
+WAV = ["wav"]
+OGG = ["ogg"]
+FLAC = ["flac"]
+AAC = ["aac"]
+M4A = ["m4a"]
+Opus = ["opus"]
+
+[file_types.Document]
+PDF = ["pdf"]
+Word = ["doc", "docx"]
+Excel = ["xls", "xlsx"]
+PowerPoint = ["ppt", "pptx"]
+OpenDocument = ["odt", "ods", "odp"]
+RTF = ["rtf"]
+Text = ["txt"]
+Markdown = ["md"]
+CSV = ["csv"]
+
+[file_types.Archive]
+ZIP = ["zip"]
+RAR = ["rar"]
+7z = ["7z"]
+TAR = ["tar"]
+GZIP = ["gz"]
+"TAR.GZ" = ["tar.gz", "tgz"]
+BZIP2 = ["bz2"]
+"TAR.BZ2" = ["tar.bz2", "tbz2"]
+XZ = ["xz"]
+"TAR.XZ" = ["tar.xz", "txz"]
+Zstandard = ["zst"]
+
+[file_types."Disk Image"]
+ISO = ["iso"]
+IMG = ["img"]
+DMG = ["dmg"]
+VHD = ["vhd", "vhdx"]
+VMDK = ["vmdk"]
+QCOW2 = ["qcow2"]
+
+[file_types.Executable]
+EXE = ["exe"]
+DLL = ["dll"]
+MSI = ["msi"]
+APK = ["apk"]
+DEB = ["deb"]
+RPM = ["rpm"]
+AppImage = ["appimage"]
+JAR = ["jar"]
+
+[file_types.Ebook]
+EPUB = ["epub"]
+MOBI = ["mobi"]
+AZW = ["azw", "azw3"]
+DjVu = ["djvu"]
+FB2 = ["fb2"]
+CBZ = ["cbz"]
+CBR = ["cbr"]
+
+[file_types.Code]
+Python = ["py"]
+Rust = ["rs"]
+C = ["c", "h"]
+"C++" = ["cpp", "cc", "hpp"]
+Java = ["java"]
+JavaScript = ["js"]
+TypeScript = ["ts"]
+Go = ["go"]
+Shell = ["sh", "bash"]
+CSS = ["css"]
+SQL = ["sql"]
+JSON = ["json"]
+YAML = ["yml", "yaml"]
+XML = ["xml"]
+
+[file_types.Font]
+TrueType = ["ttf"]
+OpenType = ["otf"]
+WOFF = ["woff", "woff2"]
+
+[file_types.Subtitle]
+SubRip = ["srt"]
+WebVTT = ["vtt"]
+"SubStation Alpha" = ["ass", "ssa"]
+
+# Report settings
+[report]
+include_nsfw_urls = true  # Whether to include URLs of NSFW content in reports
+
+# Authentication, sent to the scanned host only (command line options and
+# credentials in the URL override)
+[auth]
+# method = "basic"           # "basic" or "digest"
+# username = ""
+# password = ""
+# bearer_token = ""          # Sent as "Authorization: Bearer <token>"
+# cookie_jar = "cookies.txt" # Netscape cookie file, as written by curl -c
+#
+# [auth.headers]             # Extra headers sent to the scanned host
+# "X-Api-Key" = """#;
+
+pub fn load_config() -> Result<Table, Box<dyn Error>> {
+    let config_path = Path::new(CONFIG_PATH);
+    if !config_path.exists() {
+        println!("Config file not found, creating default config...");
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(config_path, DEFAULT_CONFIG)?;
+        println!("Default config file created at {}", CONFIG_PATH);
+    }
+
+    let config_str = fs::read_to_string(config_path)?;
+    let config: Table = toml::from_str(&config_str)?;
+    Ok(config)
+}
+
+/// Sets the string values of `[name]` in the config text `text`, keeping
+/// comments and layout, including the comments after replaced values.
+fn update_table(text: &str, name: &str, values: &[(&str, &str)]) -> Result<String, Box<dyn Error>> {
+    let mut document: DocumentMut = text.parse()?;
+    let table = document
+        .entry(name)
+        .or_insert(toml_edit::table())
+        .as_table_mut()
+        .ok_or_else(|| format!("[{}] in the config file is not a table", name))?;
+    for (key, value) in values {
+        let mut value = toml_edit::Value::from(*value);
+        if let Some(old) = table.get(key).and_then(Item::as_value) {
+            *value.decor_mut() = old.decor().clone();
+        }
+        table.insert(key, Item::Value(value));
+    }
+    Ok(document.to_string())
+}
+
+/// Writes string values of the `[name]` table back to the config file,
+/// leaving the rest of the file as the user wrote it.
+pub fn save_values(name: &str, values: &[(&str, &str)]) -> Result<(), Box<dyn Error>> {
+    let text = fs::read_to_string(CONFIG_PATH)?;
+    fs::write(CONFIG_PATH, update_table(&text, name, values)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_table_keeping_comments() {
+        let text = update_table(
+            DEFAULT_CONFIG,
+            "model",
+            &[("version", "1.2.0"), ("last_updated", "1700000000")],
+        )
+        .unwrap();
+        assert!(text.contains(
+            "version = \"1.2.0\"  # Will be updated with the actual version from GitHub"
+        ));
+        assert!(text.contains("last_updated = \"1700000000\""));
+        assert!(text.contains("# cookie_jar = \"cookies.txt\""));
+        assert_eq!(text.len(), DEFAULT_CONFIG.len() + 10);
+    }
+
+    #[test]
+    fn default_config_parses() {
+        let default: Table = toml::from_str(DEFAULT_CONFIG).unwrap();
+        assert!(default.contains_key("file_types"));
+        assert!(default.contains_key("thresholds"));
+    }
+}
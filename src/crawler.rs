@@ -0,0 +1,408 @@
+use crate::checkpoint::Checkpoint;
+use crate::listing::ListingEntry;
+use crate::report::{DirectoryReport, FileType};
+use crate::scanner::{self, DirectoryListing, ScanContext};
+use crate::shutdown;
+use crate::urls;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+
+/// Listings keyed by directory URL, or the error that stopped the fetch.
+/// They are shared so that checkpoints can hold them without a deep copy.
+pub type Listings = HashMap<String, Arc<Result<DirectoryListing, String>>>;
+
+pub struct CrawlResults {
+    pub listings: Listings,
+    pub duplicates_suppressed: usize,
+    pub loops_suppressed: usize,
+    /// Directories still queued when the crawl was interrupted.
+    pub unvisited: Vec<String>,
+}
+
+/// Canonical URLs of every directory and file seen during a crawl, shared
+/// by all workers so each one is processed exactly once.
+pub struct VisitedSet {
+    seen: Mutex<HashSet<String>>,
+    duplicates: AtomicUsize,
+}
+
+impl VisitedSet {
+    pub fn new() -> Self {
+        VisitedSet {
+            seen: Mutex::new(HashSet::new()),
+            duplicates: AtomicUsize::new(0),
+        }
+    }
+
+    /// Records `url` and returns `true` if it had not been seen before.
+    pub fn insert(&self, url: &str, is_directory: bool) -> bool {
+        let canonical = urls::canonicalize(url, is_directory);
+        let inserted = self.seen.lock().unwrap().insert(canonical);
+        if !inserted {
+            self.duplicates.fetch_add(1, Ordering::Relaxed);
+        }
+        inserted
+    }
+
+    pub fn duplicates(&self) -> usize {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+
+    /// Seeds the set from a checkpoint. The URLs are already canonical.
+    fn restore(&self, urls: Vec<String>, duplicates: usize) {
+        self.seen.lock().unwrap().extend(urls);
+        self.duplicates.store(duplicates, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryTask {
+    pub url: String,
+    pub depth: u32,
+    /// Listing fingerprints of the directories above this one.
+    pub ancestors: Vec<u64>,
+}
+
+/// Hashes the visible content of a listing. A directory whose listing is
+/// identical to one of its ancestors is assumed to be a symlink loop.
+/// Fingerprints are saved in checkpoints, so the hash must not change
+/// between builds.
+fn fingerprint(entries: &[ListingEntry]) -> u64 {
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        hasher.update(format!(
+            "{:?} {:?} {:?} {}\n",
+            entry.name, entry.size, entry.modified, entry.is_directory
+        ));
+    }
+    let digest = hasher.finalize();
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+}
+
+struct QueueState {
+    pending: VecDeque<DirectoryTask>,
+    in_flight: Vec<DirectoryTask>,
+    listings: Listings,
+    /// Directory URLs in the order their listings were recorded, so that a
+    /// checkpoint only has to pick up the ones added since the last.
+    completed: Vec<String>,
+    loops_suppressed: usize,
+}
+
+/// The checkpoint writer's own copy of the completed listings, brought up
+/// to date from `QueueState::completed` on every checkpoint.
+struct CheckpointState {
+    written: Instant,
+    /// Number of entries of `QueueState::completed` already copied.
+    copied: usize,
+    listings: Listings,
+    visited: Vec<String>,
+}
+
+/// Caps the number of concurrent fetches per host.
+struct HostLimiter {
+    limit: usize,
+    active: Mutex<HashMap<String, usize>>,
+    released: Condvar,
+}
+
+impl HostLimiter {
+    fn new(limit: usize) -> Self {
+        HostLimiter {
+            limit: limit.max(1),
+            active: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self, host: &str) {
+        let mut active = self.active.lock().unwrap();
+        while active.get(host).copied().unwrap_or(0) >= self.limit {
+            active = self.released.wait(active).unwrap();
+        }
+        *active.entry(host.to_string()).or_insert(0) += 1;
+    }
+
+    fn release(&self, host: &str) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(count) = active.get_mut(host) {
+            *count -= 1;
+        }
+        self.released.notify_all();
+    }
+}
+
+fn host_of(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?', '#']).next().unwrap_or(rest)
+}
+
+struct Crawler<'a> {
+    root: &'a str,
+    context: &'a ScanContext<'a>,
+    state: Mutex<QueueState>,
+    work_available: Condvar,
+    limiter: HostLimiter,
+    last_checkpoint: Mutex<CheckpointState>,
+}
+
+impl Crawler<'_> {
+    fn next_task(&self) -> Option<DirectoryTask> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if shutdown::requested() {
+                return None;
+            }
+            if let Some(task) = state.pending.pop_front() {
+                state.in_flight.push(task.clone());
+                return Some(task);
+            }
+            if state.in_flight.is_empty() {
+                return None;
+            }
+            state = self.work_available.wait(state).unwrap();
+        }
+    }
+
+    fn list(&self, task: &DirectoryTask) -> Result<Option<DirectoryListing>, String> {
+        println!("Scanning directory: {} (depth: {})", task.url, task.depth);
+        let (base, entries) = self
+            .context
+            .backend
+            .list(&task.url)
+            .map_err(|e| e.to_string())?;
+
+        let fingerprint = fingerprint(&entries);
+        if !entries.is_empty() && task.ancestors.contains(&fingerprint) {
+            println!("Skipping {}: listing repeats a parent directory", task.url);
+            return Ok(None);
+        }
+
+        let mut listing = scanner::process_listing(&base, entries, self.context);
+        listing.fingerprint = fingerprint;
+        Ok(Some(listing))
+    }
+
+    fn worker(&self) {
+        while let Some(task) = self.next_task() {
+            let host = host_of(&task.url).to_string();
+            self.limiter.acquire(&host);
+            let result = self.list(&task);
+            self.limiter.release(&host);
+
+            let mut state = self.state.lock().unwrap();
+            // A fetch that failed during shutdown may only have been cut
+            // short; it stays unvisited so that a resumed scan lists it.
+            if result.is_err() && shutdown::requested() {
+                state.in_flight.retain(|t| t.url != task.url);
+                state.pending.push_front(task);
+                self.work_available.notify_all();
+                continue;
+            }
+            let result = match result {
+                Ok(Some(listing)) => {
+                    if task.depth < self.context.options.max_depth {
+                        let mut ancestors = task.ancestors.clone();
+                        ancestors.push(listing.fingerprint);
+                        for url in listing.subdirectories() {
+                            state.pending.push_back(DirectoryTask {
+                                url: url.to_string(),
+                                depth: task.depth + 1,
+                                ancestors: ancestors.clone(),
+                            });
+                        }
+                    }
+                    Ok(listing)
+                }
+                Ok(None) => {
+                    state.loops_suppressed += 1;
+                    Ok(DirectoryListing::default())
+                }
+                Err(e) => {
+                    if task.depth > 0 {
+                        eprintln!("Error scanning subdirectory {}: {}", task.url, e);
+                    }
+                    Err(e)
+                }
+            };
+            state.in_flight.retain(|t| t.url != task.url);
+            state.completed.push(task.url.clone());
+            state.listings.insert(task.url, Arc::new(result));
+            self.work_available.notify_all();
+            drop(state);
+
+            self.maybe_checkpoint();
+        }
+    }
+
+    /// Writes a checkpoint if one is configured and the interval has
+    /// elapsed. Only one worker writes at a time; the others carry on.
+    fn maybe_checkpoint(&self) {
+        let Some(path) = &self.context.options.checkpoint_path else {
+            return;
+        };
+        let Ok(mut saved) = self.last_checkpoint.try_lock() else {
+            return;
+        };
+        if saved.written.elapsed() < self.context.options.checkpoint_interval {
+            return;
+        }
+
+        if let Err(e) = self.checkpoint(&mut saved).save(path) {
+            eprintln!("Failed to write checkpoint {}: {}", path, e);
+        }
+        saved.written = Instant::now();
+    }
+
+    /// Builds a checkpoint, holding the queue lock only to copy the
+    /// frontier and the listings completed since the previous checkpoint.
+    fn checkpoint(&self, saved: &mut CheckpointState) -> Checkpoint {
+        let (pending, completed, loops_suppressed) = {
+            let state = self.state.lock().unwrap();
+            // In-flight directories have not been recorded yet, so they are
+            // saved as pending and listed again on resume.
+            let pending: Vec<DirectoryTask> = state
+                .in_flight
+                .iter()
+                .chain(state.pending.iter())
+                .cloned()
+                .collect();
+            let completed: Vec<_> = state.completed[saved.copied..]
+                .iter()
+                .map(|url| (url.clone(), state.listings[url].clone()))
+                .collect();
+            saved.copied = state.completed.len();
+            (pending, completed, state.loops_suppressed)
+        };
+
+        // The visited set is rebuilt from completed listings only; entries
+        // claimed by in-flight directories must be claimable again on resume.
+        if saved.visited.is_empty() {
+            saved.visited.push(urls::canonicalize(self.root, true));
+        }
+        for (_, result) in &completed {
+            let Ok(listing) = result.as_ref() else {
+                continue;
+            };
+            for file in &listing.files {
+                let is_directory = *file.effective_type() == FileType::Directory;
+                saved
+                    .visited
+                    .push(urls::canonicalize(&file.url, is_directory));
+            }
+            for link in listing
+                .external_links
+                .iter()
+                .chain(&listing.robots_excluded)
+            {
+                saved
+                    .visited
+                    .push(urls::canonicalize(link, link.ends_with('/')));
+            }
+        }
+        saved.listings.extend(completed);
+
+        let report = scanner::aggregate(self.root, &saved.listings)
+            .map(|(report, _)| report)
+            .unwrap_or_else(|_| DirectoryReport::new());
+
+        Checkpoint {
+            root: self.root.to_string(),
+            settings: self.context.options.settings.clone(),
+            pending,
+            visited: saved.visited.clone(),
+            listings: saved.listings.clone(),
+            duplicates_suppressed: self.context.visited.duplicates(),
+            loops_suppressed,
+            report,
+        }
+    }
+}
+
+/// Fetches every directory below `root`, up to `options.max_depth`, with a
+/// bounded pool of worker threads. When `resume` is given, the crawl
+/// continues from the checkpoint's frontier instead of starting at `root`.
+pub fn crawl(root: &str, context: &ScanContext, resume: Option<Checkpoint>) -> CrawlResults {
+    let options = context.options;
+    let state = match resume {
+        Some(checkpoint) => {
+            context
+                .visited
+                .restore(checkpoint.visited, checkpoint.duplicates_suppressed);
+            QueueState {
+                pending: checkpoint.pending.into(),
+                in_flight: Vec::new(),
+                completed: checkpoint.listings.keys().cloned().collect(),
+                listings: checkpoint.listings,
+                loops_suppressed: checkpoint.loops_suppressed,
+            }
+        }
+        None => {
+            context.visited.insert(root, true);
+            QueueState {
+                pending: VecDeque::from([DirectoryTask {
+                    url: root.to_string(),
+                    depth: 0,
+                    ancestors: Vec::new(),
+                }]),
+                in_flight: Vec::new(),
+                listings: HashMap::new(),
+                completed: Vec::new(),
+                loops_suppressed: 0,
+            }
+        }
+    };
+
+    let crawler = Crawler {
+        root,
+        context,
+        state: Mutex::new(state),
+        work_available: Condvar::new(),
+        limiter: HostLimiter::new(options.per_host_concurrency),
+        last_checkpoint: Mutex::new(CheckpointState {
+            written: Instant::now(),
+            copied: 0,
+            listings: HashMap::new(),
+            visited: Vec::new(),
+        }),
+    };
+
+    thread::scope(|scope| {
+        for _ in 0..options.concurrency.max(1) {
+            scope.spawn(|| crawler.worker());
+        }
+    });
+
+    // An interrupted crawl saves its frontier; a finished one leaves
+    // nothing to resume, so its checkpoint is removed.
+    if let Some(path) = &options.checkpoint_path {
+        let finished = crawler.state.lock().unwrap().pending.is_empty();
+        let result = if finished {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result.map_err(Into::into),
+            }
+        } else {
+            let mut saved = crawler.last_checkpoint.lock().unwrap();
+            crawler.checkpoint(&mut saved).save(path)
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to update checkpoint {}: {}", path, e);
+        }
+    }
+
+    let state = crawler.state.into_inner().unwrap();
+    CrawlResults {
+        listings: state.listings,
+        duplicates_suppressed: context.visited.duplicates(),
+        loops_suppressed: state.loops_suppressed,
+        unvisited: state.pending.into_iter().map(|task| task.url).collect(),
+    }
+}
@@ -0,0 +1,246 @@
+use crate::archive;
+use crate::backend::Backend;
+use crate::pdf;
+use crate::report::FileType;
+use roxmltree::Document;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+const DCTERMS_NAMESPACE: &str = "http://purl.org/dc/terms/";
+const OOXML_APP_NAMESPACE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/extended-properties";
+const ODF_META_NAMESPACE: &str = "urn:oasis:names:tc:opendocument:xmlns:meta:1.0";
+
+/// Author, title, creation tool, page count and dates of a document.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocumentMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Application the document was created with.
+    pub creator: Option<String>,
+    /// Library that wrote a PDF, when it differs from the application.
+    pub producer: Option<String>,
+    pub pages: Option<u32>,
+    /// Creation time, as `YYYY-MM-DD HH:MM:SS` in the author's time zone.
+    pub created: Option<String>,
+    pub modified: Option<String>,
+}
+
+/// Document formats whose metadata can be read.
+enum Kind {
+    Pdf,
+    OfficeOpenXml,
+    OpenDocument,
+}
+
+fn kind(name: &str, file_type: &FileType) -> Option<Kind> {
+    let extension = name.rsplit_once('.')?.1.to_ascii_lowercase();
+    match extension.as_str() {
+        "pdf" => Some(Kind::Pdf),
+        "docx" | "docm" | "xlsx" | "xlsm" | "pptx" | "pptm" => Some(Kind::OfficeOpenXml),
+        "odt" | "ods" | "odp" => Some(Kind::OpenDocument),
+        // Files without a telling name may have been identified by content.
+        _ => match file_type.format()?.to_ascii_lowercase().as_str() {
+            "pdf" | "application/pdf" => Some(Kind::Pdf),
+            _ => None,
+        },
+    }
+}
+
+/// Whether `read` supports the file.
+pub fn is_supported(name: &str, file_type: &FileType) -> bool {
+    kind(name, file_type).is_some()
+}
+
+/// Formats an ISO 8601 time (`YYYY-MM-DDTHH:MM:SSZ`) as
+/// `YYYY-MM-DD HH:MM:SS`, leaving out fractions and the time zone.
+pub fn format_iso_time(value: String) -> String {
+    match value.get(..19) {
+        Some(time) if value.as_bytes()[10] == b'T' => time.replacen('T', " ", 1),
+        _ => value,
+    }
+}
+
+fn xml_text(xml: &Document, namespace: &str, name: &str) -> Option<String> {
+    xml.descendants()
+        .find(|n| n.tag_name().namespace() == Some(namespace) && n.tag_name().name() == name)
+        .and_then(|n| n.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// Reads the named entries of the ZIP archive at `url`. Entries missing
+/// from the archive are returned as `None`.
+fn read_entries(
+    url: &str,
+    names: &[&str],
+    backend: &dyn Backend,
+) -> Result<Vec<Option<String>>, Box<dyn Error>> {
+    let entries = archive::list_zip(url, backend)?;
+    names
+        .iter()
+        .map(|name| match entries.iter().find(|e| e.name == *name) {
+            Some(entry) => {
+                let data = archive::read_entry(url, entry, backend)?;
+                Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+            }
+            None => Ok(None),
+        })
+        .collect()
+}
+
+/// Reads `docProps/core.xml` and `docProps/app.xml` of a Word, Excel or
+/// PowerPoint file.
+fn read_office_open_xml(
+    url: &str,
+    backend: &dyn Backend,
+) -> Result<DocumentMetadata, Box<dyn Error>> {
+    let entries = read_entries(url, &["docProps/core.xml", "docProps/app.xml"], backend)?;
+    let mut metadata = DocumentMetadata::default();
+    if let Some(Ok(core)) = entries[0].as_deref().map(Document::parse) {
+        metadata.title = xml_text(&core, DC_NAMESPACE, "title");
+        metadata.author = xml_text(&core, DC_NAMESPACE, "creator");
+        metadata.created = xml_text(&core, DCTERMS_NAMESPACE, "created").map(format_iso_time);
+        metadata.modified = xml_text(&core, DCTERMS_NAMESPACE, "modified").map(format_iso_time);
+    }
+    if let Some(Ok(app)) = entries[1].as_deref().map(Document::parse) {
+        metadata.creator = xml_text(&app, OOXML_APP_NAMESPACE, "Application");
+        // Word counts pages, PowerPoint slides.
+        metadata.pages = xml_text(&app, OOXML_APP_NAMESPACE, "Pages")
+            .or_else(|| xml_text(&app, OOXML_APP_NAMESPACE, "Slides"))
+            .and_then(|count| count.parse().ok());
+    }
+    Ok(metadata)
+}
+
+/// Reads `meta.xml` of an OpenDocument text, spreadsheet or presentation.
+fn read_open_document(
+    url: &str,
+    backend: &dyn Backend,
+) -> Result<DocumentMetadata, Box<dyn Error>> {
+    let entries = read_entries(url, &["meta.xml"], backend)?;
+    let mut metadata = DocumentMetadata::default();
+    if let Some(Ok(meta)) = entries[0].as_deref().map(Document::parse) {
+        metadata.title = xml_text(&meta, DC_NAMESPACE, "title");
+        metadata.author = xml_text(&meta, ODF_META_NAMESPACE, "initial-creator")
+            .or_else(|| xml_text(&meta, DC_NAMESPACE, "creator"));
+        metadata.creator = xml_text(&meta, ODF_META_NAMESPACE, "generator");
+        metadata.created =
+            xml_text(&meta, ODF_META_NAMESPACE, "creation-date").map(format_iso_time);
+        metadata.modified = xml_text(&meta, DC_NAMESPACE, "date").map(format_iso_time);
+        metadata.pages = meta
+            .descendants()
+            .find_map(|n| n.attribute((ODF_META_NAMESPACE, "page-count")))
+            .and_then(|count| count.parse().ok());
+    }
+    Ok(metadata)
+}
+
+/// Reads the metadata of the PDF, Office Open XML or OpenDocument file at
+/// `url` with range requests. Returns `None` for other documents.
+pub fn read(
+    url: &str,
+    name: &str,
+    file_type: &FileType,
+    backend: &dyn Backend,
+) -> Result<Option<DocumentMetadata>, Box<dyn Error>> {
+    let metadata = match kind(name, file_type) {
+        Some(Kind::Pdf) => pdf::read(url, backend)?,
+        Some(Kind::OfficeOpenXml) => read_office_open_xml(url, backend)?,
+        Some(Kind::OpenDocument) => read_open_document(url, backend)?,
+        None => return Ok(None),
+    };
+    Ok(Some(metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryBackend;
+
+    /// Builds an archive holding `data` uncompressed under `name`.
+    fn stored_zip(name: &str, data: &[u8]) -> Vec<u8> {
+        let fields = |signature: u32| {
+            [
+                &signature.to_le_bytes()[..],
+                &[20, 0, 0, 0, 0, 0],
+                &[0; 8],
+                &(data.len() as u32).to_le_bytes(),
+                &(data.len() as u32).to_le_bytes(),
+                &(name.len() as u16).to_le_bytes(),
+                &[0, 0],
+            ]
+            .concat()
+        };
+        let local = [fields(0x0403_4B50), name.as_bytes().to_vec()].concat();
+        let central = [
+            &0x0201_4B50u32.to_le_bytes()[..],
+            &[20, 0],
+            &fields(0)[4..],
+            &[0; 14],
+            name.as_bytes(),
+        ]
+        .concat();
+        let end = [
+            &0x0605_4B50u32.to_le_bytes()[..],
+            &[0, 0, 0, 0, 1, 0, 1, 0],
+            &(central.len() as u32).to_le_bytes(),
+            &((local.len() + data.len()) as u32).to_le_bytes(),
+            &[0, 0],
+        ]
+        .concat();
+        [local, data.to_vec(), central, end].concat()
+    }
+
+    fn read_zipped(name: &str, entry: &str, xml: &str) -> DocumentMetadata {
+        let backend = MemoryBackend(stored_zip(entry, xml.as_bytes()));
+        let file_type = FileType::file("Document", "Word");
+        read("memory:///file", name, &file_type, &backend)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_office_open_xml_core_properties() {
+        let core = r#"<?xml version="1.0"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/">
+<dc:title>Budget</dc:title><dc:creator>Alex</dc:creator>
+<dcterms:created>2024-01-02T10:00:00Z</dcterms:created>
+</cp:coreProperties>"#;
+        let metadata = read_zipped("budget.docx", "docProps/core.xml", core);
+        assert_eq!(metadata.title.as_deref(), Some("Budget"));
+        assert_eq!(metadata.author.as_deref(), Some("Alex"));
+        assert_eq!(metadata.created.as_deref(), Some("2024-01-02 10:00:00"));
+        assert!(metadata.pages.is_none());
+    }
+
+    #[test]
+    fn reads_open_document_meta() {
+        let meta = r#"<?xml version="1.0"?>
+<office:document-meta xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:meta="urn:oasis:names:tc:opendocument:xmlns:meta:1.0" xmlns:dc="http://purl.org/dc/elements/1.1/">
+<office:meta><meta:generator>LibreOffice/7.6</meta:generator><meta:initial-creator>Sam</meta:initial-creator>
+<meta:document-statistic meta:page-count="12"/></office:meta></office:document-meta>"#;
+        let metadata = read_zipped("notes.odt", "meta.xml", meta);
+        assert_eq!(metadata.creator.as_deref(), Some("LibreOffice/7.6"));
+        assert_eq!(metadata.author.as_deref(), Some("Sam"));
+        assert_eq!(metadata.pages, Some(12));
+    }
+
+    #[test]
+    fn ignores_malformed_xml() {
+        let metadata = read_zipped("notes.odt", "meta.xml", "<office:document-meta><dc:title>");
+        assert!(metadata.title.is_none());
+        assert!(metadata.author.is_none());
+    }
+
+    #[test]
+    fn formats_iso_times() {
+        assert_eq!(
+            format_iso_time("2024-01-02T10:00:00.5+02:00".to_string()),
+            "2024-01-02 10:00:00"
+        );
+        assert_eq!(format_iso_time("2024-01-02".to_string()), "2024-01-02");
+    }
+}
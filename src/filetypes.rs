@@ -0,0 +1,101 @@
+use crate::config;
+use crate::report::FileType;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use toml::Table;
+
+// Categories the scanner itself relies on: images are NSFW-checked, audio
+// and video share ambiguous extensions, and unknown extensions are Other.
+pub const IMAGE: &str = "Image";
+pub const VIDEO: &str = "Video";
+pub const AUDIO: &str = "Audio";
+pub const DOCUMENT: &str = "Document";
+pub const ARCHIVE: &str = "Archive";
+pub const EXECUTABLE: &str = "Executable";
+pub const OTHER: &str = "Other";
+
+/// Maps file extensions to a category and format, as configured in the
+/// `[file_types]` table.
+#[derive(Debug, Clone)]
+pub struct FileTypes {
+    extensions: HashMap<String, (String, String)>,
+}
+
+impl Default for FileTypes {
+    /// The taxonomy from the default config.
+    fn default() -> Self {
+        let config: Table = toml::from_str(config::DEFAULT_CONFIG).unwrap();
+        FileTypes::from_config(&config).unwrap()
+    }
+}
+
+impl FileTypes {
+    /// Reads `[file_types.<Category>]` tables of `Format = ["ext", ...]`.
+    /// Configs without the section get the default taxonomy.
+    pub fn from_config(config: &Table) -> Result<Self, Box<dyn Error>> {
+        let Some(section) = config.get("file_types") else {
+            return Ok(FileTypes::default());
+        };
+        let section = section.as_table().ok_or("file_types must be a table")?;
+
+        let mut extensions = HashMap::new();
+        for (category, formats) in section {
+            let formats = formats
+                .as_table()
+                .ok_or_else(|| format!("file_types.{} must be a table", category))?;
+            for (format, list) in formats {
+                let list = list.as_array().ok_or_else(|| {
+                    format!(
+                        "file_types.{}.{} must be a list of extensions",
+                        category, format
+                    )
+                })?;
+                for extension in list {
+                    let extension = extension
+                        .as_str()
+                        .ok_or_else(|| {
+                            format!(
+                                "file_types.{}.{} must be a list of extensions",
+                                category, format
+                            )
+                        })?
+                        .trim_start_matches('.')
+                        .to_lowercase();
+                    if let Some((other, _)) = extensions.get(&extension) {
+                        return Err(format!(
+                            "Extension .{} is listed under both {} and {}",
+                            extension, other, category
+                        )
+                        .into());
+                    }
+                    extensions.insert(extension, (category.clone(), format.clone()));
+                }
+            }
+        }
+        Ok(FileTypes { extensions })
+    }
+
+    /// Classifies a file by its extension. Compound extensions such as
+    /// `tar.gz` are tried before the last one; names without an extension
+    /// are taken to be directories.
+    pub fn classify(&self, filename: &str) -> FileType {
+        let Some(extension) = Path::new(filename)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .filter(|ext| !ext.is_empty())
+        else {
+            return FileType::Directory;
+        };
+
+        let name = filename.to_lowercase();
+        let known = name
+            .match_indices('.')
+            .filter(|(i, _)| *i > 0)
+            .find_map(|(i, _)| self.extensions.get(&name[i + 1..]));
+        match known {
+            Some((category, format)) => FileType::file(category, format),
+            None => FileType::file(OTHER, &extension.to_lowercase()),
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use crate::listing;
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use toml::Table;
+
+/// Filter patterns as written in the `[scanner]` config table or on the
+/// command line, before compilation.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FilterRules {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub include_regex: Vec<String>,
+    pub exclude_regex: Vec<String>,
+    pub include_extensions: Vec<String>,
+    pub exclude_extensions: Vec<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+}
+
+fn string_list(table: &Table, key: &str) -> Vec<String> {
+    table
+        .get(key)
+        .and_then(|v| v.as_array())
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn size_value(table: &Table, key: &str) -> Option<u64> {
+    match table.get(key)? {
+        toml::Value::Integer(n) if *n > 0 => Some(*n as u64),
+        toml::Value::String(s) => listing::parse_size(s),
+        _ => None,
+    }
+}
+
+impl FilterRules {
+    pub fn from_config(config: &Table) -> Self {
+        let Some(scanner) = config.get("scanner").and_then(|v| v.as_table()) else {
+            return FilterRules::default();
+        };
+
+        FilterRules {
+            include: string_list(scanner, "include"),
+            exclude: string_list(scanner, "exclude"),
+            include_regex: string_list(scanner, "include_regex"),
+            exclude_regex: string_list(scanner, "exclude_regex"),
+            include_extensions: string_list(scanner, "include_extensions"),
+            exclude_extensions: string_list(scanner, "exclude_extensions"),
+            min_size: size_value(scanner, "min_size"),
+            max_size: size_value(scanner, "max_size"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    /// Globs containing a `/` match the decoded URL path, others the name.
+    Glob {
+        matcher: GlobMatcher,
+        match_path: bool,
+        literal_prefix: String,
+    },
+    Regex(Regex),
+    Extension(String),
+}
+
+impl Pattern {
+    fn glob(pattern: &str) -> Result<Self, Box<dyn Error>> {
+        let matcher = Glob::new(pattern)?.compile_matcher();
+        let literal_prefix = pattern
+            .split(['*', '?', '[', '{'])
+            .next()
+            .unwrap_or("")
+            .to_string();
+        Ok(Pattern::Glob {
+            matcher,
+            match_path: pattern.contains('/'),
+            literal_prefix,
+        })
+    }
+
+    fn matches(&self, path: &str, name: &str) -> bool {
+        match self {
+            Pattern::Glob {
+                matcher,
+                match_path,
+                ..
+            } => matcher.is_match(if *match_path { path } else { name }),
+            Pattern::Regex(regex) => regex.is_match(path),
+            Pattern::Extension(ext) => name
+                .rsplit_once('.')
+                .is_some_and(|(_, e)| e.eq_ignore_ascii_case(ext)),
+        }
+    }
+
+    /// Whether files matching this pattern can exist below `dir_path`.
+    fn may_match_below(&self, dir_path: &str) -> bool {
+        match self {
+            Pattern::Glob {
+                match_path: true,
+                literal_prefix,
+                ..
+            } => dir_path.starts_with(literal_prefix) || literal_prefix.starts_with(dir_path),
+            _ => true,
+        }
+    }
+}
+
+/// Compiled include/exclude rules. A file is counted when it matches no
+/// exclude rule and at least one rule of every kind of include rule given
+/// (globs, regexes, extensions). A directory is descended into unless an
+/// exclude rule matches it or no path glob can match anything below it.
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    /// Include rules grouped by kind; each non-empty group must match.
+    include: Vec<Vec<Pattern>>,
+    exclude: Vec<Pattern>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+}
+
+fn compile(
+    globs: &[String],
+    regexes: &[String],
+    extensions: &[String],
+) -> Result<Vec<Pattern>, Box<dyn Error>> {
+    let mut patterns = Vec::new();
+    for glob in globs {
+        patterns.push(Pattern::glob(glob)?);
+    }
+    for regex in regexes {
+        patterns.push(Pattern::Regex(Regex::new(regex)?));
+    }
+    for ext in extensions {
+        patterns.push(Pattern::Extension(ext.trim_start_matches('.').to_string()));
+    }
+    Ok(patterns)
+}
+
+impl Filters {
+    pub fn new(rules: &FilterRules) -> Result<Self, Box<dyn Error>> {
+        let include = [
+            compile(&rules.include, &[], &[])?,
+            compile(&[], &rules.include_regex, &[])?,
+            compile(&[], &[], &rules.include_extensions)?,
+        ];
+
+        Ok(Filters {
+            include: include.into_iter().filter(|g| !g.is_empty()).collect(),
+            exclude: compile(
+                &rules.exclude,
+                &rules.exclude_regex,
+                &rules.exclude_extensions,
+            )?,
+            min_size: rules.min_size,
+            max_size: rules.max_size,
+        })
+    }
+
+    pub fn allows_directory(&self, path: &str) -> bool {
+        let trimmed = path.trim_end_matches('/');
+        let name = trimmed.rsplit('/').next().unwrap_or(trimmed);
+        let excluded = self.exclude.iter().any(|p| {
+            !matches!(p, Pattern::Extension(_))
+                && (p.matches(path, name) || p.matches(trimmed, name))
+        });
+        !excluded
+            && self
+                .include
+                .iter()
+                .all(|group| group.iter().any(|p| p.may_match_below(path)))
+    }
+
+    pub fn allows_file(&self, path: &str, name: &str) -> bool {
+        !self.exclude.iter().any(|p| p.matches(path, name))
+            && self
+                .include
+                .iter()
+                .all(|group| group.iter().any(|p| p.matches(path, name)))
+    }
+
+    pub fn allows_size(&self, size: u64) -> bool {
+        self.min_size.is_none_or(|min| size >= min) && self.max_size.is_none_or(|max| size <= max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn requires_every_kind_of_include() {
+        let filters = Filters::new(&FilterRules {
+            include: strings(&["/pub/linux/**"]),
+            include_extensions: strings(&[".iso"]),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(filters.allows_file("/pub/linux/debian/netinst.ISO", "netinst.ISO"));
+        assert!(!filters.allows_file("/pub/linux/README", "README"));
+        assert!(!filters.allows_file("/pub/bsd/install.iso", "install.iso"));
+    }
+
+    #[test]
+    fn prunes_directories_outside_path_globs() {
+        let filters = Filters::new(&FilterRules {
+            include: strings(&["/pub/linux/**"]),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(filters.allows_directory("/"));
+        assert!(filters.allows_directory("/pub/"));
+        assert!(filters.allows_directory("/pub/linux/debian/"));
+        assert!(!filters.allows_directory("/pub/bsd/"));
+    }
+
+    #[test]
+    fn excludes_files_and_directories() {
+        let filters = Filters::new(&FilterRules {
+            exclude: strings(&["*.tmp", "/private/**", "backup"]),
+            exclude_regex: strings(&["(?i)/old-[0-9]+/"]),
+            exclude_extensions: strings(&["log"]),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!filters.allows_file("/a/cache.tmp", "cache.tmp"));
+        assert!(!filters.allows_file("/a/server.LOG", "server.LOG"));
+        assert!(!filters.allows_file("/Old-2019/a.txt", "a.txt"));
+        assert!(filters.allows_file("/a/notes.txt", "notes.txt"));
+        assert!(!filters.allows_directory("/private/"));
+        assert!(!filters.allows_directory("/home/backup/"));
+        // Extension rules apply to files only.
+        assert!(filters.allows_directory("/logs.log/"));
+    }
+
+    #[test]
+    fn limits_sizes() {
+        let filters = Filters::new(&FilterRules {
+            min_size: Some(10),
+            max_size: Some(100),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!filters.allows_size(9));
+        assert!(filters.allows_size(10));
+        assert!(filters.allows_size(100));
+        assert!(!filters.allows_size(101));
+    }
+
+    #[test]
+    fn reads_rules_from_config() {
+        let config: Table = toml::from_str(
+            r#"
+            [scanner]
+            include = ["*.iso"]
+            exclude_regex = ["/tmp/"]
+            min_size = "1M"
+            max_size = 8589934592
+            "#,
+        )
+        .unwrap();
+        let rules = FilterRules::from_config(&config);
+        assert_eq!(rules.include, ["*.iso"]);
+        assert_eq!(rules.exclude_regex, ["/tmp/"]);
+        assert_eq!(rules.min_size, Some(1 << 20));
+        assert_eq!(rules.max_size, Some(8 << 30));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let rules = FilterRules {
+            include_regex: strings(&["(unclosed"]),
+            ..Default::default()
+        };
+        assert!(Filters::new(&rules).is_err());
+    }
+}
@@ -0,0 +1,642 @@
+use crate::backend::{Backend, RawListing};
+use crate::listing::ListingEntry;
+use crate::probe::Probe;
+use crate::ratelimit::RateLimiter;
+use crate::urls;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::Url;
+
+const DEFAULT_PORT: u16 = 21;
+
+/// The error for a control connection the server has closed, or announced
+/// it will close with a 421 reply. Only these are retried on a new one.
+fn connection_lost(reason: &str) -> Box<dyn Error> {
+    io::Error::new(io::ErrorKind::ConnectionAborted, reason.to_string()).into()
+}
+
+/// Fails unless the reply code is in the `class`xx range.
+fn expect(reply: (u16, String), class: u16) -> Result<(), Box<dyn Error>> {
+    if reply.0 / 100 == class {
+        Ok(())
+    } else {
+        Err(format!("FTP error: {}", reply.1.trim_end()).into())
+    }
+}
+
+/// One logged-in control connection.
+struct FtpConnection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    peer: IpAddr,
+    timeout: Duration,
+    /// The server announced `MLST` (and with it `MLSD`) in its `FEAT` reply.
+    mlsd: bool,
+    /// Cleared after an aborted transfer, since servers differ in how many
+    /// replies follow an `ABOR`.
+    reusable: bool,
+}
+
+impl FtpConnection {
+    fn connect(
+        host: &str,
+        port: u16,
+        username: &str,
+        password: &str,
+        timeout: Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let addr = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format!("Could not resolve {}", host))?;
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let mut connection = FtpConnection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            peer: addr.ip(),
+            timeout,
+            mlsd: false,
+            reusable: true,
+        };
+
+        expect(connection.read_reply()?, 2)?;
+        let reply = connection.command(&format!("USER {}", username))?;
+        if reply.0 == 331 {
+            let reply = connection.command(&format!("PASS {}", password))?;
+            expect(reply, 2)?;
+        } else {
+            expect(reply, 2)?;
+        }
+
+        let (code, features) = connection.command("FEAT")?;
+        connection.mlsd = code == 211 && features.to_ascii_uppercase().contains("MLST");
+        // Servers that do not know these commands simply refuse them.
+        connection.command("OPTS UTF8 ON")?;
+        let reply = connection.command("TYPE I")?;
+        expect(reply, 2)?;
+        Ok(connection)
+    }
+
+    /// Reads one reply, joining the lines of multi-line replies.
+    fn read_reply(&mut self) -> Result<(u16, String), Box<dyn Error>> {
+        let mut text = String::new();
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(connection_lost("FTP server closed the connection"));
+        }
+        let code: u16 = line
+            .get(..3)
+            .and_then(|c| c.parse().ok())
+            .ok_or_else(|| format!("Invalid FTP reply: {}", line.trim_end()))?;
+        text.push_str(&line);
+
+        if line.as_bytes().get(3) == Some(&b'-') {
+            let end = format!("{} ", code);
+            loop {
+                line.clear();
+                if self.reader.read_line(&mut line)? == 0 {
+                    return Err(connection_lost("FTP server closed the connection"));
+                }
+                text.push_str(&line);
+                if line.starts_with(&end) {
+                    break;
+                }
+            }
+        }
+        if code == 421 {
+            return Err(connection_lost(text.trim_end()));
+        }
+        Ok((code, text))
+    }
+
+    fn command(&mut self, command: &str) -> Result<(u16, String), Box<dyn Error>> {
+        self.writer
+            .write_all(format!("{}\r\n", command).as_bytes())?;
+        self.read_reply()
+    }
+
+    /// Opens a passive data connection, preferring `EPSV`. The address in a
+    /// `PASV` reply is ignored in favour of the control connection's peer,
+    /// since servers behind NAT often report a private address.
+    fn open_data(&mut self) -> Result<TcpStream, Box<dyn Error>> {
+        let (code, text) = self.command("EPSV")?;
+        let port = if code == 229 {
+            text.split('|')
+                .nth(3)
+                .and_then(|p| p.parse::<u16>().ok())
+                .ok_or_else(|| format!("Invalid EPSV reply: {}", text.trim_end()))?
+        } else {
+            let (code, text) = self.command("PASV")?;
+            if code != 227 {
+                return Err(format!("FTP error: {}", text.trim_end()).into());
+            }
+            let numbers: Vec<&str> = text
+                .split(|c: char| !c.is_ascii_digit())
+                .filter(|n| !n.is_empty())
+                .collect();
+            let octets = match numbers.as_slice() {
+                [.., p1, p2] if numbers.len() >= 7 => {
+                    p1.parse::<u8>().ok().zip(p2.parse::<u8>().ok())
+                }
+                _ => None,
+            };
+            let (p1, p2) =
+                octets.ok_or_else(|| format!("Invalid PASV reply: {}", text.trim_end()))?;
+            u16::from(p1) << 8 | u16::from(p2)
+        };
+
+        let stream = TcpStream::connect_timeout(&SocketAddr::new(self.peer, port), self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        Ok(stream)
+    }
+
+    /// Runs a command that sends its result over a data connection.
+    fn transfer(&mut self, command: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut data = self.open_data()?;
+        let reply = self.command(command)?;
+        expect(reply, 1)?;
+
+        let mut buf = Vec::new();
+        data.read_to_end(&mut buf)?;
+        drop(data);
+
+        let reply = self.read_reply()?;
+        expect(reply, 2)?;
+        Ok(buf)
+    }
+
+    /// Reads at most `limit` bytes of a file, starting at `offset` via
+    /// `REST`. A transfer cut short is aborted with `ABOR`, which is
+    /// answered by a 426 and/or a 2xx reply.
+    fn transfer_range(
+        &mut self,
+        path: &str,
+        offset: u64,
+        limit: usize,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut data = self.open_data()?;
+        if offset > 0 {
+            let reply = self.command(&format!("REST {}", offset))?;
+            expect(reply, 3)?;
+        }
+        let reply = self.command(&format!("RETR {}", path))?;
+        expect(reply, 1)?;
+
+        let mut buf = Vec::with_capacity(limit);
+        (&mut data).take(limit as u64).read_to_end(&mut buf)?;
+        let complete = buf.len() < limit || data.read(&mut [0u8; 1])? == 0;
+        drop(data);
+
+        if !complete {
+            self.writer.write_all(b"ABOR\r\n")?;
+            self.reusable = false;
+        }
+        loop {
+            let reply = self.read_reply()?;
+            if complete || reply.0 != 426 {
+                expect(reply, 2)?;
+                return Ok(buf);
+            }
+        }
+    }
+
+    fn size(&mut self, path: &str) -> Result<Option<u64>, Box<dyn Error>> {
+        let (code, text) = self.command(&format!("SIZE {}", path))?;
+        if code != 213 {
+            return Ok(None);
+        }
+        Ok(text.get(4..).and_then(|s| s.trim().parse().ok()))
+    }
+}
+
+/// Formats an MLSD `modify` fact (`YYYYMMDDHHMMSS[.sss]`, UTC).
+fn format_mlsd_time(value: &str) -> String {
+    let digits = value.split('.').next().unwrap_or(value);
+    if digits.len() != 14 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return value.to_string();
+    }
+    format!(
+        "{}-{}-{} {}:{}:{}",
+        &digits[0..4],
+        &digits[4..6],
+        &digits[6..8],
+        &digits[8..10],
+        &digits[10..12],
+        &digits[12..14]
+    )
+}
+
+fn entry(
+    name: &str,
+    size: Option<u64>,
+    modified: Option<String>,
+    is_directory: bool,
+) -> ListingEntry {
+    let mut href = urls::encode_segment(name);
+    if is_directory {
+        href.push('/');
+    }
+    ListingEntry {
+        name: name.to_string(),
+        href,
+        size: if is_directory { None } else { size },
+        modified,
+        content_type: None,
+        etag: None,
+        is_directory,
+    }
+}
+
+/// Parses `MLSD` output: `type=file;size=123;modify=20240101120000; name`.
+/// Returns the entries and the number of symbolic links left out.
+fn parse_mlsd(text: &str) -> (Vec<ListingEntry>, usize) {
+    let mut entries = Vec::new();
+    let mut links = 0;
+    for line in text.lines() {
+        let Some((facts, name)) = line.split_once(' ') else {
+            continue;
+        };
+        let mut kind = "";
+        let mut size = None;
+        let mut modified = None;
+        for fact in facts.split(';') {
+            let Some((key, value)) = fact.split_once('=') else {
+                continue;
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "type" => kind = value,
+                "size" => size = value.parse().ok(),
+                "modify" => modified = Some(format_mlsd_time(value)),
+                _ => {}
+            }
+        }
+        match kind.to_ascii_lowercase().as_str() {
+            "file" => entries.push(entry(name, size, modified, false)),
+            "dir" => entries.push(entry(name, None, modified, true)),
+            // Links appear as e.g. `OS.unix=slink:/target`.
+            kind if kind.contains("link") => links += 1,
+            // cdir/pdir are the directory itself and its parent; devices
+            // are not followed.
+            _ => {}
+        }
+    }
+    (entries, links)
+}
+
+/// Returns the rest of `line` after `count` whitespace-separated fields,
+/// keeping spaces inside file names intact.
+fn after_fields(line: &str, count: usize) -> Option<&str> {
+    let mut rest = line.trim_start();
+    for _ in 0..count {
+        let end = rest.find(char::is_whitespace)?;
+        rest = rest[end..].trim_start();
+    }
+    (!rest.is_empty()).then_some(rest)
+}
+
+/// Parses `LIST` output in Unix `ls -l` or DOS/IIS format. Returns the
+/// entries and the number of symbolic links left out, since a link's
+/// listed size is that of its target path and it may point anywhere.
+fn parse_list(text: &str) -> (Vec<ListingEntry>, usize) {
+    let mut entries = Vec::new();
+    let mut links = 0;
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let parsed = match line.chars().next() {
+            // lrwxrwxrwx 1 user group 7 Jan 01 12:00 name -> target
+            Some('l') if fields.len() >= 9 => {
+                links += 1;
+                None
+            }
+            // drwxr-xr-x 2 user group 4096 Jan 01 12:00 name
+            Some(kind @ ('d' | '-')) if fields.len() >= 9 => after_fields(line, 8).map(|name| {
+                let modified = fields[5..8].join(" ");
+                (name, fields[4].parse().ok(), modified, kind == 'd')
+            }),
+            // 01-15-24  03:04PM       <DIR>          name
+            Some(c) if c.is_ascii_digit() && fields.len() >= 4 => {
+                after_fields(line, 3).map(|name| {
+                    let modified = format!("{} {}", fields[0], fields[1]);
+                    let is_directory = fields[2].eq_ignore_ascii_case("<DIR>");
+                    (name, fields[2].parse().ok(), modified, is_directory)
+                })
+            }
+            _ => None,
+        };
+
+        if let Some((name, size, modified, is_directory)) = parsed
+            && name != "."
+            && name != ".."
+        {
+            entries.push(entry(name, size, Some(modified), is_directory));
+        }
+    }
+    (entries, links)
+}
+
+/// Lists FTP servers with `MLSD`, falling back to `LIST`, and downloads
+/// files with `RETR`. Logged-in connections are pooled per host.
+pub struct FtpBackend {
+    username: String,
+    password: String,
+    timeout: Duration,
+    limiter: Arc<RateLimiter>,
+    pool: Mutex<HashMap<String, Vec<FtpConnection>>>,
+}
+
+impl FtpBackend {
+    /// Without credentials the anonymous account is used.
+    pub fn new(
+        username: Option<String>,
+        password: Option<String>,
+        timeout: Duration,
+        limiter: Arc<RateLimiter>,
+    ) -> Self {
+        FtpBackend {
+            username: username.unwrap_or_else(|| "anonymous".to_string()),
+            password: password.unwrap_or_else(|| "anonymous@".to_string()),
+            timeout,
+            limiter,
+            pool: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Runs `operation` with the decoded path of `url` on a pooled
+    /// connection. An I/O error on a reused connection is retried once on a
+    /// fresh one, since idle connections are often closed by the server;
+    /// error replies such as 550 are not.
+    /// Paths with line breaks are refused, as they would end the command
+    /// and start another.
+    fn with_connection<T>(
+        &self,
+        url: &str,
+        operation: impl Fn(&mut FtpConnection, &str) -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        let parsed = Url::parse(url)?;
+        let host = parsed.host_str().ok_or("FTP URL has no host")?;
+        let key = urls::host_key(&parsed).unwrap_or_default();
+        let path = urls::decoded_path(url);
+        if path.contains(['\r', '\n']) {
+            return Err(format!("Invalid FTP path (line break): {}", url).into());
+        }
+
+        self.limiter.wait(url);
+        let pooled = self.pool.lock().unwrap().get_mut(&key).and_then(Vec::pop);
+        let reused = pooled.is_some();
+        let mut connection = match pooled {
+            Some(connection) => connection,
+            None => self.connect(host, parsed.port())?,
+        };
+
+        let result = match operation(&mut connection, &path) {
+            Err(e) if reused && e.is::<io::Error>() => {
+                connection = self.connect(host, parsed.port())?;
+                operation(&mut connection, &path)
+            }
+            result => result,
+        };
+        if result.is_ok() && connection.reusable {
+            self.pool
+                .lock()
+                .unwrap()
+                .entry(key)
+                .or_default()
+                .push(connection);
+        }
+        result
+    }
+
+    fn connect(&self, host: &str, port: Option<u16>) -> Result<FtpConnection, Box<dyn Error>> {
+        FtpConnection::connect(
+            host,
+            port.unwrap_or(DEFAULT_PORT),
+            &self.username,
+            &self.password,
+            self.timeout,
+        )
+    }
+}
+
+impl Backend for FtpBackend {
+    fn list(&self, url: &str) -> Result<RawListing, Box<dyn Error>> {
+        let ((entries, links), method) = self.with_connection(url, |connection, path| {
+            if connection.mlsd {
+                let text = connection.transfer(&format!("MLSD {}", path))?;
+                return Ok((parse_mlsd(&String::from_utf8_lossy(&text)), "MLSD"));
+            }
+            let text = connection.transfer(&format!("LIST {}", path))?;
+            Ok((parse_list(&String::from_utf8_lossy(&text)), "LIST"))
+        })?;
+        println!(
+            "Parsed {} entries from {} (FTP {})",
+            entries.len(),
+            url,
+            method
+        );
+        if links > 0 {
+            println!("Skipped {} symbolic links in {}", links, url);
+        }
+
+        let base = match url.ends_with('/') {
+            true => url.to_string(),
+            false => format!("{}/", url),
+        };
+        Ok((base, entries))
+    }
+
+    fn probe(&self, url: &str) -> Result<Probe, Box<dyn Error>> {
+        let size = self.with_connection(url, |connection, path| connection.size(path))?;
+        Ok(Probe {
+            size,
+            content_type: None,
+        })
+    }
+
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.with_connection(url, |connection, path| {
+            connection.transfer(&format!("RETR {}", path))
+        })
+    }
+
+    fn fetch_range(
+        &self,
+        url: &str,
+        offset: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.with_connection(url, |connection, path| {
+            connection.transfer_range(path, offset, length)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serves one session of a stand-in FTP server, answering `PASV` with
+    /// `pasv` for the data port, `LIST` with `listing` and `RETR` with 550.
+    /// Returns the commands received.
+    fn stand_in(
+        pasv: fn(u16) -> String,
+        listing: &'static str,
+    ) -> (u16, thread::JoinHandle<Vec<String>>) {
+        let control = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = control.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let data = TcpListener::bind("127.0.0.1:0").unwrap();
+            let data_port = data.local_addr().unwrap().port();
+            let (stream, _) = control.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reply = |text: String| writer.write_all(format!("{}\r\n", text).as_bytes());
+            reply("220 Stand-in ready".to_string()).unwrap();
+            let mut commands = Vec::new();
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let verb = line.split(' ').next().unwrap_or_default().to_string();
+                commands.push(line);
+                let sent = match verb.as_str() {
+                    "USER" => reply("331 Password required".to_string()),
+                    "PASS" => reply("230 Logged in".to_string()),
+                    "FEAT" => reply("211 No features".to_string()),
+                    "EPSV" => reply("500 Unknown command".to_string()),
+                    "PASV" => reply(pasv(data_port)),
+                    "LIST" => {
+                        reply("150 Listing".to_string()).unwrap();
+                        let (mut stream, _) = data.accept().unwrap();
+                        stream.write_all(listing.as_bytes()).unwrap();
+                        drop(stream);
+                        reply("226 Done".to_string())
+                    }
+                    "RETR" => reply("550 No such file".to_string()),
+                    _ => reply("200 OK".to_string()),
+                };
+                if sent.is_err() {
+                    break;
+                }
+            }
+            commands
+        });
+        (port, handle)
+    }
+
+    fn backend() -> FtpBackend {
+        let limiter = Arc::new(RateLimiter::new(None, Duration::ZERO));
+        FtpBackend::new(None, None, Duration::from_secs(5), limiter)
+    }
+
+    #[test]
+    fn lists_directory_over_pasv() {
+        let (port, server) = stand_in(
+            |port| {
+                format!(
+                    "227 Entering Passive Mode (127,0,0,1,{},{})",
+                    port >> 8,
+                    port & 0xFF
+                )
+            },
+            "drwxr-xr-x 2 ftp ftp 4096 Jan 01 12:00 docs\r\n\
+             -rw-r--r-- 1 ftp ftp 123 Jan 01 12:00 read me.txt\r\n",
+        );
+        let backend = backend();
+        let (base, entries) = backend
+            .list(&format!("ftp://127.0.0.1:{}/pub", port))
+            .unwrap();
+        drop(backend);
+        assert_eq!(base, format!("ftp://127.0.0.1:{}/pub/", port));
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_directory);
+        assert_eq!(entries[1].name, "read me.txt");
+        assert_eq!(entries[1].size, Some(123));
+        let commands = server.join().unwrap();
+        assert!(commands.contains(&"LIST /pub".to_string()));
+    }
+
+    #[test]
+    fn does_not_retry_error_replies() {
+        let (port, server) = stand_in(
+            |port| {
+                format!(
+                    "227 Entering Passive Mode (127,0,0,1,{},{})",
+                    port >> 8,
+                    port & 0xFF
+                )
+            },
+            "",
+        );
+        let backend = backend();
+        backend
+            .list(&format!("ftp://127.0.0.1:{}/pub", port))
+            .unwrap();
+        let result = backend.fetch(&format!("ftp://127.0.0.1:{}/pub/gone.iso", port));
+        drop(backend);
+        assert!(result.unwrap_err().to_string().contains("550"));
+        let commands = server.join().unwrap();
+        assert_eq!(commands.iter().filter(|c| c.starts_with("RETR")).count(), 1);
+    }
+
+    #[test]
+    fn rejects_pasv_port_out_of_range() {
+        let (port, server) = stand_in(
+            |_| "227 Entering Passive Mode (127,0,0,1,300,1)".to_string(),
+            "",
+        );
+        let backend = backend();
+        let result = backend.list(&format!("ftp://127.0.0.1:{}/pub", port));
+        drop(backend);
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Invalid PASV reply")
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn rejects_line_breaks_in_paths() {
+        let backend = backend();
+        let result = backend.fetch("ftp://127.0.0.1:9/a%0D%0ADELE%20b");
+        assert!(result.unwrap_err().to_string().contains("line break"));
+    }
+
+    #[test]
+    fn parses_unix_and_dos_listings() {
+        let (entries, links) = parse_list(
+            "total 8\n\
+             lrwxrwxrwx 1 ftp ftp 7 Jan 01 12:00 latest -> v2\n\
+             01-15-24  03:04PM       <DIR>          Old Files\n\
+             01-15-24  03:04PM                  512 notes.txt\n",
+        );
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["Old Files", "notes.txt"]);
+        assert_eq!(links, 1);
+        assert!(entries[0].is_directory);
+        assert_eq!(entries[1].size, Some(512));
+    }
+
+    #[test]
+    fn parses_mlsd_listing() {
+        let (entries, links) = parse_mlsd(
+            "type=cdir; .\r\n\
+             type=dir;modify=20240101120000; sub\r\n\
+             type=file;size=42;modify=20240102030405.123; a;b.txt\r\n\
+             type=OS.unix=slink:/srv/a;modify=20240101120000; latest\r\n",
+        );
+        assert_eq!(entries.len(), 2);
+        assert_eq!(links, 1);
+        assert_eq!(entries[1].name, "a;b.txt");
+        assert_eq!(entries[1].modified.as_deref(), Some("2024-01-02 03:04:05"));
+    }
+}
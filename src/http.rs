@@ -0,0 +1,273 @@
+use crate::auth::Auth;
+use crate::ratelimit::RateLimiter;
+use crate::shutdown;
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Certificate, Identity, NoProxy, Proxy, StatusCode};
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fs;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+pub const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Upper bound on how long a server-provided `Retry-After` is honoured.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// Proxy and TLS settings shared by every client the program builds,
+/// including the one that downloads the NSFW model.
+#[derive(Default)]
+pub struct NetworkOptions {
+    /// `http://`, `https://`, `socks5://` or `socks5h://` proxy URL. Without
+    /// it the `HTTP_PROXY`/`HTTPS_PROXY` environment variables apply.
+    pub proxy: Option<String>,
+    /// Comma-separated hosts, domains and CIDR ranges that bypass `proxy`.
+    pub no_proxy: Option<String>,
+    /// PEM file with extra root certificates to trust.
+    pub ca_bundle: Option<String>,
+    /// Client certificate, either PEM (with the key in `client_key` or in
+    /// the same file) or PKCS#12 (`.p12`/`.pfx`).
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    pub cert_password: Option<String>,
+    /// Accept invalid certificates and host names.
+    pub insecure: bool,
+}
+
+fn read_file(path: &str, what: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    fs::read(path).map_err(|e| format!("Failed to read {} {}: {}", what, path, e).into())
+}
+
+/// Returns the PEM blocks of `pem` whose label contains `label`.
+fn pem_blocks(pem: &str, label: &str) -> String {
+    let mut blocks = String::new();
+    let mut inside = false;
+    for line in pem.lines() {
+        if line.starts_with("-----BEGIN ") {
+            inside = line.contains(label);
+        }
+        if inside {
+            blocks.push_str(line);
+            blocks.push('\n');
+        }
+        if line.starts_with("-----END ") {
+            inside = false;
+        }
+    }
+    blocks
+}
+
+impl NetworkOptions {
+    fn identity(&self, cert_path: &str) -> Result<Identity, Box<dyn Error>> {
+        let cert = read_file(cert_path, "client certificate")?;
+        let lower = cert_path.to_ascii_lowercase();
+        if lower.ends_with(".p12") || lower.ends_with(".pfx") {
+            let password = self.cert_password.as_deref().unwrap_or("");
+            return Ok(Identity::from_pkcs12_der(&cert, password)?);
+        }
+
+        let cert = String::from_utf8(cert)?;
+        let key = match &self.client_key {
+            Some(key_path) => String::from_utf8(read_file(key_path, "client key")?)?,
+            None => cert.clone(),
+        };
+        let key = pem_blocks(&key, "PRIVATE KEY");
+        if key.is_empty() {
+            return Err(
+                format!("No private key found for client certificate {}", cert_path).into(),
+            );
+        }
+        Ok(Identity::from_pkcs8_pem(
+            pem_blocks(&cert, "CERTIFICATE").as_bytes(),
+            key.as_bytes(),
+        )?)
+    }
+
+    /// Starts a client builder with the proxy and TLS settings applied.
+    pub fn client_builder(&self) -> Result<ClientBuilder, Box<dyn Error>> {
+        let mut builder = Client::builder();
+
+        if let Some(proxy_url) = &self.proxy {
+            let proxy = Proxy::all(proxy_url)
+                .map_err(|_| "Invalid proxy URL (expected http://, https:// or socks5://)")?;
+            builder = builder
+                .proxy(proxy.no_proxy(self.no_proxy.as_deref().and_then(NoProxy::from_string)));
+        }
+
+        if let Some(path) = &self.ca_bundle {
+            for certificate in Certificate::from_pem_bundle(&read_file(path, "CA bundle")?)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        if let Some(path) = &self.client_cert {
+            builder = builder.identity(self.identity(path)?);
+        }
+
+        if self.insecure {
+            builder = builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+
+        Ok(builder)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled for every further attempt.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Randomize each delay to between half and all of its nominal value,
+    /// so parallel workers do not retry in lockstep.
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let random = RandomState::new().hash_one(SystemTime::now());
+        let fraction = 0.5 + (random % 1000) as f64 / 2000.0;
+        delay.mul_f64(fraction)
+    }
+}
+
+/// 5xx and 429 responses are retried, except those saying the server will
+/// never handle the request (`501 Not Implemented`, `505 HTTP Version Not
+/// Supported`).
+fn is_transient_status(status: StatusCode) -> bool {
+    (status.is_server_error()
+        && status != StatusCode::NOT_IMPLEMENTED
+        && status != StatusCode::HTTP_VERSION_NOT_SUPPORTED)
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn is_transient_error(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect() || error.is_request()
+}
+
+/// Parses `Retry-After` as either delta-seconds or an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds).min(MAX_RETRY_AFTER));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    let wait = date.duration_since(SystemTime::now()).unwrap_or_default();
+    Some(wait.min(MAX_RETRY_AFTER))
+}
+
+/// The HTTP client shared by the scanner and the NSFW detector. Every
+/// request goes through [`HttpClient::execute`], which adds credentials,
+/// waits for the rate limiter and retries timeouts, connection failures,
+/// 5xx and 429 responses according to the policy.
+pub struct HttpClient {
+    client: Client,
+    retry: RetryPolicy,
+    limiter: Arc<RateLimiter>,
+    auth: Auth,
+}
+
+impl HttpClient {
+    pub fn new(client: Client, retry: RetryPolicy, limiter: Arc<RateLimiter>, auth: Auth) -> Self {
+        HttpClient {
+            client,
+            retry,
+            limiter,
+            auth,
+        }
+    }
+
+    /// Sends the request produced by `build`, rebuilding it for each retry.
+    /// Once the attempts are exhausted the last response is returned as is,
+    /// so callers still see the final status code. A stop request ends the
+    /// wait for a retry with an error.
+    pub fn execute<F>(&self, build: F) -> Result<Response, Box<dyn Error>>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        let max_attempts = self.retry.max_attempts.max(1);
+        let mut attempt = 1;
+        let mut answered_challenge = false;
+        loop {
+            let mut request = build(&self.client).build()?;
+            self.auth.apply(&mut request)?;
+            let url = request.url().to_string();
+            self.limiter.wait(&url);
+            let (delay, reason) = match self.client.execute(request) {
+                // A Digest challenge is answered once, without counting as
+                // a retry; a second 401 means the credentials are wrong.
+                Ok(response)
+                    if response.status() == StatusCode::UNAUTHORIZED
+                        && !answered_challenge
+                        && self.auth.accept_challenge(&response) =>
+                {
+                    answered_challenge = true;
+                    continue;
+                }
+                Ok(response) if is_transient_status(response.status()) => {
+                    if attempt >= max_attempts {
+                        return Ok(response);
+                    }
+                    let delay =
+                        retry_after(&response).unwrap_or_else(|| self.retry.backoff(attempt));
+                    (delay, response.status().to_string())
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if is_transient_error(&e) && attempt < max_attempts => {
+                    (self.retry.backoff(attempt), e.to_string())
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            eprintln!(
+                "Retrying {} in {:.1}s (attempt {}/{}): {}",
+                url,
+                delay.as_secs_f64(),
+                attempt + 1,
+                max_attempts,
+                reason
+            );
+            if !shutdown::sleep(delay) {
+                return Err(format!("Interrupted before retrying {}: {}", url, reason).into());
+            }
+            attempt += 1;
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Result<Response, Box<dyn Error>> {
+        self.execute(|client| client.get(url))
+    }
+
+    pub fn head(&self, url: &str) -> Result<Response, Box<dyn Error>> {
+        self.execute(|client| client.head(url))
+    }
+}
+
+#[cfg(test)]
+impl HttpClient {
+    /// A client without retries or rate limit, for tests against local
+    /// stand-in servers.
+    pub fn for_tests(auth: Auth) -> Self {
+        let retry = RetryPolicy {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            jitter: false,
+        };
+        let limiter = Arc::new(RateLimiter::new(None, Duration::ZERO));
+        HttpClient::new(Client::new(), retry, limiter, auth)
+    }
+}
@@ -0,0 +1,543 @@
+use crate::backend::Backend;
+use image::ImageReader;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::error::Error;
+use std::io::Cursor;
+
+/// Bytes read from the start of an image that is not downloaded.
+const HEADER_PREFIX: usize = 64 * 1024;
+/// Most range requests made for headers past the prefix.
+const MAX_FETCHES: usize = 16;
+/// Longest single read past the prefix. EXIF values and IFDs are far
+/// smaller.
+const MAX_READ: usize = 64 * 1024;
+/// Most JPEG segments or PNG and WebP chunks walked.
+const MAX_SEGMENTS: usize = 64;
+
+// TIFF tags used from IFD0, the Exif IFD and the GPS IFD.
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_GPS_LATITUDE_REF: u16 = 1;
+const TAG_GPS_LATITUDE: u16 = 2;
+const TAG_GPS_LONGITUDE_REF: u16 = 3;
+const TAG_GPS_LONGITUDE: u16 = 4;
+
+/// Resolution classes by megapixels, with the pixel count each one stops
+/// below, in report order.
+pub const RESOLUTION_CLASSES: &[(u64, &str)] = &[
+    (1_000_000, "under 1 MP"),
+    (4_000_000, "1-4 MP"),
+    (12_000_000, "4-12 MP"),
+    (24_000_000, "12-24 MP"),
+    (u64::MAX, "24 MP and more"),
+];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// What an image's headers reveal about it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImageMetadata {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Camera make and model from EXIF.
+    pub camera: Option<String>,
+    /// Capture time from EXIF, as `YYYY-MM-DD HH:MM:SS` in camera time.
+    pub taken: Option<String>,
+    pub gps: Option<GpsPosition>,
+}
+
+impl ImageMetadata {
+    /// The resolution class of the image, when its dimensions are known.
+    pub fn resolution_class(&self) -> Option<&'static str> {
+        let pixels = self.width? as u64 * self.height? as u64;
+        RESOLUTION_CLASSES
+            .iter()
+            .find(|(limit, _)| pixels < *limit)
+            .map(|(_, label)| *label)
+    }
+
+    /// Position, camera and capture time, for the list of geotagged images.
+    pub fn gps_summary(&self) -> Option<String> {
+        let gps = self.gps?;
+        let mut summary = format!("{:.6}, {:.6}", gps.latitude, gps.longitude);
+        let details: Vec<&str> = [self.camera.as_deref(), self.taken.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        if !details.is_empty() {
+            summary.push_str(&format!(" ({})", details.join(", ")));
+        }
+        Some(summary)
+    }
+}
+
+/// An image's bytes: all of them after a download, or the first ones with
+/// ranges fetched as the headers point past them.
+struct Source<'a> {
+    data: &'a [u8],
+    /// Where the rest comes from, when `data` is only a prefix.
+    remote: Option<(&'a str, &'a dyn Backend)>,
+    fetches: Cell<usize>,
+}
+
+impl<'a> Source<'a> {
+    fn get(&self, offset: usize, length: usize) -> Option<Cow<'a, [u8]>> {
+        let data = self.data;
+        if let Some(bytes) = data.get(offset..offset.checked_add(length)?) {
+            return Some(Cow::Borrowed(bytes));
+        }
+        let (url, backend) = self.remote?;
+        if length > MAX_READ || self.fetches.get() >= MAX_FETCHES {
+            return None;
+        }
+        self.fetches.set(self.fetches.get() + 1);
+        let bytes = backend.fetch_range(url, offset as u64, length).ok()?;
+        (bytes.len() == length).then_some(Cow::Owned(bytes))
+    }
+}
+
+/// A TIFF structure, the container EXIF data is stored in.
+struct Tiff<'a> {
+    source: &'a Source<'a>,
+    /// Where the TIFF header lies in the file. Offsets inside the structure
+    /// are relative to it.
+    start: usize,
+    big_endian: bool,
+}
+
+/// One IFD entry: tag, field type, value count, and the value itself or
+/// the offset of it when it does not fit in four bytes.
+struct Field {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    value: [u8; 4],
+}
+
+impl<'a> Tiff<'a> {
+    fn new(source: &'a Source<'a>, start: usize) -> Option<Self> {
+        let big_endian = match source.get(start, 4)?.as_ref() {
+            b"II*\0" => false,
+            b"MM\0*" => true,
+            _ => return None,
+        };
+        Some(Tiff {
+            source,
+            start,
+            big_endian,
+        })
+    }
+
+    fn bytes(&self, offset: usize, length: usize) -> Option<Cow<'a, [u8]>> {
+        self.source.get(self.start.checked_add(offset)?, length)
+    }
+
+    fn u16_from(&self, bytes: &[u8]) -> Option<u16> {
+        let bytes = bytes.get(..2)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32_from(&self, bytes: &[u8]) -> Option<u32> {
+        let bytes = bytes.get(..4)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    fn fields(&self, ifd: usize) -> Vec<Field> {
+        let count = self
+            .bytes(ifd, 2)
+            .and_then(|bytes| self.u16_from(&bytes))
+            .unwrap_or(0) as usize;
+        let Some(entries) = self.bytes(ifd + 2, count * 12) else {
+            return Vec::new();
+        };
+        entries
+            .chunks_exact(12)
+            .filter_map(|entry| {
+                Some(Field {
+                    tag: self.u16_from(entry)?,
+                    kind: self.u16_from(&entry[2..])?,
+                    count: self.u32_from(&entry[4..])?,
+                    value: entry[8..].try_into().ok()?,
+                })
+            })
+            .collect()
+    }
+
+    /// A field's value, which is inline when it fits in four bytes.
+    fn value(&self, field: &Field, size: usize) -> Option<Cow<'a, [u8]>> {
+        match size <= 4 {
+            true => Some(Cow::Owned(field.value[..size].to_vec())),
+            false => self.bytes(self.pointer(field)?, size),
+        }
+    }
+
+    fn ascii(&self, field: &Field) -> Option<String> {
+        let bytes = self.value(field, field.count as usize)?;
+        let text = String::from_utf8_lossy(&bytes);
+        let text = text.trim_end_matches('\0').trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+
+    fn rationals(&self, field: &Field) -> Option<Vec<f64>> {
+        if field.kind != 5 {
+            return None;
+        }
+        let bytes = self.value(field, (field.count as usize).checked_mul(8)?)?;
+        bytes
+            .chunks_exact(8)
+            .map(|rational| {
+                let numerator = self.u32_from(rational)? as f64;
+                let denominator = self.u32_from(&rational[4..])? as f64;
+                Some(if denominator == 0.0 {
+                    0.0
+                } else {
+                    numerator / denominator
+                })
+            })
+            .collect()
+    }
+
+    fn pointer(&self, field: &Field) -> Option<usize> {
+        self.u32_from(&field.value).map(|o| o as usize)
+    }
+}
+
+/// Walks the segments of a JPEG up to its frame header. Returns where the
+/// EXIF data starts, and sets the dimensions from the frame header if they
+/// are not known yet.
+fn find_jpeg_exif(source: &Source, metadata: &mut ImageMetadata) -> Option<usize> {
+    let mut exif = None;
+    let mut pos = 2;
+    for _ in 0..MAX_SEGMENTS {
+        let Some(header) = source.get(pos, 4) else {
+            break;
+        };
+        let marker = header[1];
+        // Start of scan: the headers are over.
+        if header[0] != 0xFF || marker == 0xDA {
+            break;
+        }
+        let length = u16::from_be_bytes([header[2], header[3]]) as usize;
+        match marker {
+            0xE1 if exif.is_none() => {
+                if source.get(pos + 4, 6).as_deref() == Some(b"Exif\0\0") {
+                    exif = Some(pos + 10);
+                }
+                if metadata.width.is_some() {
+                    break;
+                }
+            }
+            // Start of frame, except for the DHT, JPG and DAC markers that
+            // share the range.
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                if metadata.width.is_none()
+                    && let Some(frame) = source.get(pos + 5, 4)
+                {
+                    metadata.height = Some(u16::from_be_bytes([frame[0], frame[1]]).into());
+                    metadata.width = Some(u16::from_be_bytes([frame[2], frame[3]]).into());
+                }
+                break;
+            }
+            _ => {}
+        }
+        pos += 2 + length;
+    }
+    exif
+}
+
+/// Finds where the TIFF block holding the EXIF data of a JPEG, PNG, WebP
+/// or TIFF image starts.
+fn find_exif(source: &Source, metadata: &mut ImageMetadata) -> Option<usize> {
+    let start = source.get(0, 12)?;
+    if start.starts_with(b"II*\0") || start.starts_with(b"MM\0*") {
+        return Some(0);
+    }
+    if start.starts_with(b"\xFF\xD8") {
+        return find_jpeg_exif(source, metadata);
+    }
+    if start.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let mut pos = 8;
+        for _ in 0..MAX_SEGMENTS {
+            let header = source.get(pos, 8)?;
+            let length = u32::from_be_bytes(header[..4].try_into().ok()?) as usize;
+            match &header[4..] {
+                b"eXIf" => return Some(pos + 8),
+                b"IEND" => return None,
+                _ => pos = pos.checked_add(12 + length)?,
+            }
+        }
+        return None;
+    }
+    if start.starts_with(b"RIFF") && &start[8..12] == b"WEBP" {
+        let mut pos = 12;
+        for _ in 0..MAX_SEGMENTS {
+            let header = source.get(pos, 8)?;
+            let length = u32::from_le_bytes(header[4..].try_into().ok()?) as usize;
+            if &header[..4] == b"EXIF" {
+                return match source.get(pos + 8, 6).as_deref() == Some(b"Exif\0\0") {
+                    true => Some(pos + 14),
+                    false => Some(pos + 8),
+                };
+            }
+            pos = pos.checked_add(8 + length + length % 2)?;
+        }
+    }
+    None
+}
+
+/// Converts degrees, minutes and seconds to signed decimal degrees.
+fn coordinate(values: &[f64], reference: Option<String>, negative: &str) -> Option<f64> {
+    let [degrees, minutes, seconds] = values else {
+        return None;
+    };
+    let value = degrees + minutes / 60.0 + seconds / 3600.0;
+    Some(match reference.as_deref() == Some(negative) {
+        true => -value,
+        false => value,
+    })
+}
+
+/// Formats an EXIF time (`YYYY:MM:DD HH:MM:SS`) as `YYYY-MM-DD HH:MM:SS`.
+fn format_exif_time(value: String) -> String {
+    match value.get(..10) {
+        Some(date) if value.len() >= 19 => format!("{}{}", date.replace(':', "-"), &value[10..]),
+        _ => value,
+    }
+}
+
+fn read_exif(tiff: &Tiff, metadata: &mut ImageMetadata) {
+    let Some(ifd0) = tiff.bytes(4, 4).and_then(|bytes| tiff.u32_from(&bytes)) else {
+        return;
+    };
+    let (mut make, mut model) = (None, None);
+    for field in tiff.fields(ifd0 as usize) {
+        match field.tag {
+            TAG_MAKE => make = tiff.ascii(&field),
+            TAG_MODEL => model = tiff.ascii(&field),
+            TAG_DATE_TIME if metadata.taken.is_none() => {
+                metadata.taken = tiff.ascii(&field).map(format_exif_time)
+            }
+            TAG_EXIF_IFD => {
+                let exif = tiff.pointer(&field).map(|ifd| tiff.fields(ifd));
+                for field in exif.iter().flatten() {
+                    if field.tag == TAG_DATE_TIME_ORIGINAL {
+                        metadata.taken = tiff.ascii(field).map(format_exif_time);
+                    }
+                }
+            }
+            TAG_GPS_IFD => {
+                let gps = tiff.pointer(&field).map(|ifd| tiff.fields(ifd));
+                let (mut latitude, mut longitude) = (None, None);
+                let (mut latitude_ref, mut longitude_ref) = (None, None);
+                for field in gps.iter().flatten() {
+                    match field.tag {
+                        TAG_GPS_LATITUDE_REF => latitude_ref = tiff.ascii(field),
+                        TAG_GPS_LATITUDE => latitude = tiff.rationals(field),
+                        TAG_GPS_LONGITUDE_REF => longitude_ref = tiff.ascii(field),
+                        TAG_GPS_LONGITUDE => longitude = tiff.rationals(field),
+                        _ => {}
+                    }
+                }
+                if let (Some(latitude), Some(longitude)) = (
+                    latitude.and_then(|v| coordinate(&v, latitude_ref, "S")),
+                    longitude.and_then(|v| coordinate(&v, longitude_ref, "W")),
+                ) {
+                    metadata.gps = Some(GpsPosition {
+                        latitude,
+                        longitude,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    // Many cameras repeat the make at the start of the model.
+    metadata.camera = match (make, model) {
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => model.or(make),
+    };
+}
+
+fn read_source(source: &Source) -> ImageMetadata {
+    let mut metadata = ImageMetadata::default();
+    if let Ok((width, height)) = ImageReader::new(Cursor::new(source.data))
+        .with_guessed_format()
+        .map_err(image::ImageError::from)
+        .and_then(|reader| reader.into_dimensions())
+    {
+        metadata.width = Some(width);
+        metadata.height = Some(height);
+    }
+    if let Some(tiff) = find_exif(source, &mut metadata).and_then(|start| Tiff::new(source, start))
+    {
+        read_exif(&tiff, &mut metadata);
+    }
+    metadata
+}
+
+/// Reads the dimensions and EXIF data of a downloaded image. Only the
+/// headers are parsed; the image itself is not decoded.
+pub fn read(data: &[u8]) -> ImageMetadata {
+    read_source(&Source {
+        data,
+        remote: None,
+        fetches: Cell::new(0),
+    })
+}
+
+/// Reads the dimensions and EXIF data of an image without downloading it:
+/// its first 64 KiB, then ranges wherever the headers point past them.
+pub fn fetch(url: &str, backend: &dyn Backend) -> Result<ImageMetadata, Box<dyn Error>> {
+    let prefix = backend.fetch_prefix(url, HEADER_PREFIX)?;
+    // A shorter prefix is the whole file.
+    let remote = (prefix.len() >= HEADER_PREFIX).then_some((url, backend));
+    Ok(read_source(&Source {
+        data: &prefix,
+        remote,
+        fetches: Cell::new(0),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryBackend;
+
+    fn entry(tag: u16, kind: u16, count: u32, value: u32) -> Vec<u8> {
+        let mut entry = tag.to_le_bytes().to_vec();
+        entry.extend(kind.to_le_bytes());
+        entry.extend(count.to_le_bytes());
+        entry.extend(value.to_le_bytes());
+        entry
+    }
+
+    fn rationals(values: [u32; 3]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|v| [*v, 1])
+            .flat_map(u32::to_le_bytes)
+            .collect()
+    }
+
+    /// A little-endian TIFF block with make, model and a GPS position of
+    /// 48°30' N, 2°15'36" W.
+    fn tiff() -> Vec<u8> {
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        tiff.extend(3u16.to_le_bytes());
+        tiff.extend(entry(TAG_MAKE, 2, 6, 50));
+        tiff.extend(entry(TAG_MODEL, 2, 13, 56));
+        tiff.extend(entry(TAG_GPS_IFD, 4, 1, 70));
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(b"Canon\0Canon EOS 5D\0\0");
+        tiff.extend(4u16.to_le_bytes());
+        tiff.extend(entry(TAG_GPS_LATITUDE_REF, 2, 2, u32::from(b'N')));
+        tiff.extend(entry(TAG_GPS_LATITUDE, 5, 3, 124));
+        tiff.extend(entry(TAG_GPS_LONGITUDE_REF, 2, 2, u32::from(b'W')));
+        tiff.extend(entry(TAG_GPS_LONGITUDE, 5, 3, 148));
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(rationals([48, 30, 0]));
+        tiff.extend(rationals([2, 15, 36]));
+        tiff
+    }
+
+    fn segment(marker: u8, data: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend((data.len() as u16 + 2).to_be_bytes());
+        segment.extend(data);
+        segment
+    }
+
+    /// A 640x480 JPEG header, with `padding` segments of 40000 bytes in
+    /// front of the EXIF data.
+    fn jpeg(padding: usize) -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8];
+        for _ in 0..padding {
+            jpeg.extend(segment(0xE2, &[0; 40000]));
+        }
+        jpeg.extend(segment(0xE1, &[b"Exif\0\0".as_slice(), &tiff()].concat()));
+        jpeg.extend(segment(
+            0xC0,
+            &[8, 1, 0xE0, 2, 0x80, 3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1],
+        ));
+        jpeg.extend(segment(0xDA, &[0; 10]));
+        jpeg.extend([0xFF, 0xD9]);
+        jpeg
+    }
+
+    fn assert_full(metadata: &ImageMetadata) {
+        assert_eq!((metadata.width, metadata.height), (Some(640), Some(480)));
+        assert_eq!(metadata.camera.as_deref(), Some("Canon EOS 5D"));
+        let gps = metadata.gps.unwrap();
+        assert!((gps.latitude - 48.5).abs() < 1e-9);
+        assert!((gps.longitude + 2.26).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reads_jpeg_headers() {
+        assert_full(&read(&jpeg(0)));
+    }
+
+    #[test]
+    fn fetches_headers_past_prefix() {
+        let data = jpeg(2);
+        assert!(data.len() > HEADER_PREFIX);
+        let metadata = fetch("http://example.com/a.jpg", &MemoryBackend(data)).unwrap();
+        assert_full(&metadata);
+    }
+
+    #[test]
+    fn reads_png_exif_chunk() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let tiff = tiff();
+        png.extend((tiff.len() as u32).to_be_bytes());
+        png.extend(b"eXIf");
+        png.extend(&tiff);
+        png.extend([0; 4]);
+        png.extend(b"\0\0\0\0IEND\xAE\x42\x60\x82");
+        let metadata = read(&png);
+        assert_eq!(metadata.camera.as_deref(), Some("Canon EOS 5D"));
+        assert!(metadata.gps.is_some());
+    }
+
+    #[test]
+    fn survives_truncated_headers() {
+        let data = jpeg(0);
+        // The TIFF block follows the SOI and APP1 markers and "Exif\0\0".
+        let tiff_end = 12 + tiff().len();
+        for length in 0..data.len() {
+            let metadata = read(&data[..length]);
+            assert_eq!(metadata.gps.is_some(), length >= tiff_end, "{}", length);
+        }
+    }
+
+    #[test]
+    fn ignores_offsets_past_end() {
+        let mut data = jpeg(0);
+        // IFD0 offset, after the JPEG and APP1 markers, "Exif\0\0" and the
+        // TIFF byte order.
+        data[18..22].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+        let metadata = read(&data);
+        assert_eq!(metadata.camera, None);
+        assert!(metadata.gps.is_none());
+        let metadata = fetch("http://example.com/a.jpg", &MemoryBackend(data)).unwrap();
+        assert_eq!(metadata.camera, None);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::http::HttpClient;
+use crate::listing::{self, ListingEntry};
+use crate::urls;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{Value as JsonValue, json};
+use std::error::Error;
+use std::time::{Duration, UNIX_EPOCH};
+use url::Url;
+
+/// `Accept` header for index pages: Caddy `browse` lists a directory as
+/// JSON when asked to, other servers send their HTML page. nginx's
+/// `autoindex_format json` ignores it and is recognized by the response.
+pub const ACCEPT: &str = "application/json, text/html;q=0.9, */*;q=0.8";
+
+lazy_static! {
+    static ref H5AI_SCRIPT: Regex = Regex::new(r#"["']([^"']*/_h5ai/public/)"#).unwrap();
+}
+
+/// Whether a response is a JSON listing: by its `Content-Type`, or by a
+/// body that is a JSON array for servers that label it as something else.
+pub fn is_listing(content_type: Option<&str>, body: &str) -> bool {
+    content_type.is_some_and(|t| t.contains("json")) || body.trim_start().starts_with('[')
+}
+
+fn entry(name: &str, href: String, size: Option<u64>, modified: Option<String>) -> ListingEntry {
+    let is_directory = href.ends_with('/');
+    ListingEntry {
+        name: name.trim_end_matches('/').to_string(),
+        href,
+        size: if is_directory { None } else { size },
+        modified,
+        content_type: None,
+        etag: None,
+        is_directory,
+    }
+}
+
+fn string_field(item: &JsonValue, key: &str) -> Option<String> {
+    item.get(key).and_then(|v| v.as_str()).map(str::to_string)
+}
+
+/// nginx `autoindex_format json`:
+/// `{"name": "x", "type": "file", "mtime": "<RFC 1123>", "size": 1}`.
+fn nginx_entry(item: &JsonValue) -> Option<ListingEntry> {
+    let name = item.get("name")?.as_str()?;
+    let mut href = urls::encode_segment(name);
+    if item.get("type")?.as_str()? == "directory" {
+        href.push('/');
+    }
+    Some(entry(
+        name,
+        href,
+        item.get("size").and_then(|v| v.as_u64()),
+        string_field(item, "mtime"),
+    ))
+}
+
+/// Caddy `browse` with `Accept: application/json`:
+/// `{"name": "x/", "url": "./x/", "size": 1, "mod_time": "<RFC 3339>", "is_dir": true}`.
+fn caddy_entry(item: &JsonValue) -> Option<ListingEntry> {
+    let name = item.get("name")?.as_str()?;
+    let is_directory = item.get("is_dir")?.as_bool()?;
+    let mut href = string_field(item, "url").unwrap_or_else(|| urls::encode_segment(name));
+    if is_directory && !href.ends_with('/') {
+        href.push('/');
+    }
+    Some(entry(
+        name,
+        href,
+        item.get("size").and_then(|v| v.as_u64()),
+        string_field(item, "mod_time"),
+    ))
+}
+
+/// Parses a JSON directory listing, returning the name of the format and
+/// its entries.
+pub fn parse(body: &str) -> Result<(&'static str, Vec<ListingEntry>), Box<dyn Error>> {
+    let json: JsonValue = serde_json::from_str(body)?;
+    let items = json.as_array().ok_or("JSON listing is not an array")?;
+
+    let (format, parse_item): (&str, fn(&JsonValue) -> Option<ListingEntry>) = match items.first() {
+        Some(first) if first.get("is_dir").is_some() => ("Caddy JSON", caddy_entry),
+        _ => ("nginx JSON", nginx_entry),
+    };
+    Ok((format, items.iter().filter_map(parse_item).collect()))
+}
+
+/// Finds the h5ai API endpoint referenced by an h5ai index page, e.g.
+/// `<script src="/_h5ai/public/js/scripts.js">`.
+pub fn h5ai_api(page_url: &str, body: &str) -> Option<String> {
+    let public = H5AI_SCRIPT.captures(body)?.get(1)?.as_str();
+    urls::resolve(page_url, &format!("{}index.php", public))
+}
+
+/// Lists a directory through the h5ai API. h5ai answers with the requested
+/// folder, its parents and its children; only the children are kept.
+pub fn list_h5ai(
+    api: &str,
+    url: &str,
+    client: &HttpClient,
+) -> Result<Vec<ListingEntry>, Box<dyn Error>> {
+    let path = Url::parse(url)?.path().to_string();
+    let request = json!({"action": "get", "items": {"href": path, "what": 1}});
+    let response = client.execute(|c| {
+        c.post(api)
+            .header(CONTENT_TYPE, "application/json")
+            .body(request.to_string())
+    })?;
+    if !response.status().is_success() {
+        return Err(format!("h5ai API request failed: {}", response.status()).into());
+    }
+
+    let json: JsonValue = response.json()?;
+    let items = json
+        .get("items")
+        .and_then(|v| v.as_array())
+        .ok_or("h5ai response has no items")?;
+    let own_path = urls::decoded_path(url);
+
+    let mut entries = Vec::new();
+    for item in items {
+        let Some(href) = string_field(item, "href") else {
+            continue;
+        };
+        let Some(resolved) = urls::resolve(url, &href) else {
+            continue;
+        };
+        let decoded = urls::decoded_path(&resolved);
+        let parent = match decoded.trim_end_matches('/').rsplit_once('/') {
+            Some((parent, _)) => format!("{}/", parent),
+            None => continue,
+        };
+        if parent != own_path || decoded == own_path {
+            continue;
+        }
+
+        // Modification times are milliseconds since the epoch.
+        let modified = item
+            .get("time")
+            .and_then(|v| v.as_u64())
+            .map(|ms| httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_millis(ms)));
+        entries.push(entry(
+            &listing::name_from_href(&href),
+            href,
+            item.get("size").and_then(|v| v.as_u64()),
+            modified,
+        ));
+    }
+    Ok(entries)
+}
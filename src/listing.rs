@@ -0,0 +1,564 @@
+use percent_encoding::percent_decode_str;
+use scraper::{ElementRef, Html, Node, Selector};
+
+#[derive(Debug, Clone)]
+pub struct ListingEntry {
+    pub name: String,
+    pub href: String,
+    pub size: Option<u64>,
+    pub modified: Option<String>,
+    /// MIME type reported by the server, when the listing includes one.
+    pub content_type: Option<String>,
+    /// Entity tag reported by the server, e.g. an S3 object's ETag.
+    pub etag: Option<String>,
+    pub is_directory: bool,
+}
+
+pub struct Listing {
+    pub parser: &'static str,
+    /// Value of the page's `<base href>`, if any.
+    pub base_href: Option<String>,
+    pub entries: Vec<ListingEntry>,
+}
+
+pub trait ListingParser {
+    fn name(&self) -> &'static str;
+    fn detect(&self, server: Option<&str>, document: &Html) -> bool;
+    fn parse(&self, document: &Html) -> Vec<ListingEntry>;
+}
+
+// Parsers are tried in order; the generic parser is the fallback and
+// must stay last because it accepts any page.
+const PARSERS: &[&dyn ListingParser] = &[
+    &ApacheParser,
+    &NginxParser,
+    &IisParser,
+    &LighttpdParser,
+    &CaddyParser,
+    &GenericParser,
+];
+
+/// Fingerprints an index page and extracts its entries with the best
+/// matching server-specific parser.
+pub fn parse_listing(server: Option<&str>, body: &str) -> Listing {
+    let document = Html::parse_document(body);
+    let base_href = document
+        .select(&selector("base[href]"))
+        .next()
+        .and_then(|base| base.value().attr("href"))
+        .map(str::to_string);
+
+    for parser in PARSERS {
+        if !parser.detect(server, &document) {
+            continue;
+        }
+        let entries = parser.parse(&document);
+        if !entries.is_empty() {
+            return Listing {
+                parser: parser.name(),
+                base_href,
+                entries,
+            };
+        }
+    }
+
+    Listing {
+        parser: GenericParser.name(),
+        base_href,
+        entries: GenericParser.parse(&document),
+    }
+}
+
+/// Parses human-readable sizes such as `12345`, `1.2K`, `4.0 MiB` or `3 GB`.
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    if number.is_empty() {
+        return None;
+    }
+    let number: f64 = number.replace(',', "").parse().ok()?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" | "bytes" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return None,
+    };
+
+    Some((number * multiplier as f64) as u64)
+}
+
+fn server_matches(server: Option<&str>, name: &str) -> bool {
+    server.is_some_and(|s| s.to_ascii_lowercase().contains(name))
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).unwrap()
+}
+
+fn is_navigation_link(href: &str, text: &str) -> bool {
+    let text = text.trim().to_ascii_lowercase();
+    href == "../"
+        || href == ".."
+        || href == "/"
+        || href.starts_with('?')
+        || href.starts_with('#')
+        || text == "parent directory"
+        || text == "[to parent directory]"
+        || text == "../"
+}
+
+/// Derives a display name from the last path segment of `href`, decoding
+/// percent-escapes (`My%20File.txt` becomes `My File.txt`).
+pub fn name_from_href(href: &str) -> String {
+    let path = href.split(['?', '#']).next().unwrap_or(href);
+    let segment = path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(path);
+    percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+fn entry_from_link(link: ElementRef) -> Option<ListingEntry> {
+    let href = link.value().attr("href")?;
+    let text: String = link.text().collect();
+    if is_navigation_link(href, &text) {
+        return None;
+    }
+
+    Some(ListingEntry {
+        name: name_from_href(href),
+        href: href.to_string(),
+        size: None,
+        modified: None,
+        content_type: None,
+        etag: None,
+        is_directory: href.ends_with('/'),
+    })
+}
+
+fn text_of(node: Option<&Node>) -> Option<String> {
+    node.and_then(|n| n.as_text().map(|t| t.to_string()))
+}
+
+/// Splits a `date time size` fragment from a `<pre>` listing line. The last
+/// whitespace-separated token is the size, everything before it the date.
+fn split_metadata(fragment: &str) -> (Option<String>, Option<String>) {
+    let tokens: Vec<&str> = fragment.split_whitespace().collect();
+    match tokens.split_last() {
+        Some((size, date)) if !date.is_empty() => (Some(date.join(" ")), Some(size.to_string())),
+        Some((size, _)) => (None, Some(size.to_string())),
+        None => (None, None),
+    }
+}
+
+/// `<pre>` listings where the metadata follows the link on the same line
+/// (nginx and Apache without `HTMLTable`).
+fn parse_pre_trailing(document: &Html) -> Vec<ListingEntry> {
+    let mut entries = Vec::new();
+    for link in document.select(&selector("pre a")) {
+        let Some(mut entry) = entry_from_link(link) else {
+            continue;
+        };
+
+        let trailing = text_of(link.next_sibling().map(|n| n.value())).unwrap_or_default();
+        let line = trailing.lines().next().unwrap_or("");
+        // Apache may append a description after the size, so only the
+        // first three tokens (date, time, size) are considered.
+        let fields: Vec<&str> = line.split_whitespace().take(3).collect();
+        let (modified, size) = split_metadata(&fields.join(" "));
+        entry.modified = modified;
+        entry.size = size.as_deref().and_then(parse_size);
+        entries.push(entry);
+    }
+    entries
+}
+
+/// `<table>` listings whose header row names the columns.
+fn parse_table(document: &Html) -> Vec<ListingEntry> {
+    let mut entries = Vec::new();
+    let th = selector("th");
+    let td = selector("td");
+    let a = selector("a[href]");
+
+    for table in document.select(&selector("table")) {
+        let headers: Vec<String> = table
+            .select(&th)
+            .map(|h| h.text().collect::<String>().trim().to_ascii_lowercase())
+            .collect();
+        let name_col = headers.iter().position(|h| h.starts_with("name"));
+        let size_col = headers.iter().position(|h| h.starts_with("size"));
+        let modified_col = headers
+            .iter()
+            .position(|h| h.contains("modified") || h.contains("date"));
+        let Some(name_col) = name_col else {
+            continue;
+        };
+
+        for row in table.select(&selector("tr")) {
+            let cells: Vec<ElementRef> = row.select(&td).collect();
+            let Some(link) = cells.get(name_col).and_then(|c| c.select(&a).next()) else {
+                continue;
+            };
+            let Some(mut entry) = entry_from_link(link) else {
+                continue;
+            };
+
+            let cell_text = |col: Option<usize>| {
+                col.and_then(|i| cells.get(i))
+                    .map(|c| c.text().collect::<String>().trim().to_string())
+                    .filter(|t| !t.is_empty())
+            };
+            entry.size = cell_text(size_col).as_deref().and_then(parse_size);
+            entry.modified = cell_text(modified_col);
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+struct ApacheParser;
+
+impl ListingParser for ApacheParser {
+    fn name(&self) -> &'static str {
+        "Apache"
+    }
+
+    fn detect(&self, server: Option<&str>, document: &Html) -> bool {
+        server_matches(server, "apache")
+            || document
+                .select(&selector("address"))
+                .any(|a| a.text().collect::<String>().contains("Apache"))
+            || document
+                .select(&selector("a[href^='?C=']"))
+                .next()
+                .is_some()
+    }
+
+    fn parse(&self, document: &Html) -> Vec<ListingEntry> {
+        let entries = parse_table(document);
+        if !entries.is_empty() {
+            return entries;
+        }
+        parse_pre_trailing(document)
+    }
+}
+
+struct NginxParser;
+
+impl ListingParser for NginxParser {
+    fn name(&self) -> &'static str {
+        "nginx"
+    }
+
+    fn detect(&self, server: Option<&str>, document: &Html) -> bool {
+        server_matches(server, "nginx")
+            || (document
+                .select(&selector("h1"))
+                .any(|h| h.text().collect::<String>().starts_with("Index of"))
+                && document.select(&selector("pre")).next().is_some())
+    }
+
+    fn parse(&self, document: &Html) -> Vec<ListingEntry> {
+        parse_pre_trailing(document)
+    }
+}
+
+struct IisParser;
+
+impl ListingParser for IisParser {
+    fn name(&self) -> &'static str {
+        "IIS"
+    }
+
+    fn detect(&self, server: Option<&str>, document: &Html) -> bool {
+        server_matches(server, "microsoft-iis")
+            || document.select(&selector("pre a")).any(|a| {
+                a.text()
+                    .collect::<String>()
+                    .contains("[To Parent Directory]")
+            })
+    }
+
+    fn parse(&self, document: &Html) -> Vec<ListingEntry> {
+        let mut entries = Vec::new();
+        for link in document.select(&selector("pre a")) {
+            let Some(mut entry) = entry_from_link(link) else {
+                continue;
+            };
+
+            // IIS prints `date time AM/PM size|<dir>` before the link.
+            let leading = text_of(link.prev_sibling().map(|n| n.value())).unwrap_or_default();
+            let line = leading.lines().last().unwrap_or("");
+            let (modified, size) = split_metadata(line);
+            if size.as_deref() == Some("<dir>") {
+                entry.is_directory = true;
+            } else {
+                entry.size = size.as_deref().and_then(parse_size);
+            }
+            entry.modified = modified;
+            entries.push(entry);
+        }
+        entries
+    }
+}
+
+struct LighttpdParser;
+
+impl ListingParser for LighttpdParser {
+    fn name(&self) -> &'static str {
+        "lighttpd"
+    }
+
+    fn detect(&self, server: Option<&str>, document: &Html) -> bool {
+        server_matches(server, "lighttpd")
+            || document
+                .select(&selector("table[summary='Directory Listing']"))
+                .next()
+                .is_some()
+    }
+
+    fn parse(&self, document: &Html) -> Vec<ListingEntry> {
+        let mut entries = Vec::new();
+        let size = selector("td.s");
+        let modified = selector("td.m");
+        let kind = selector("td.t");
+
+        for row in document.select(&selector("tr")) {
+            let Some(link) = row.select(&selector("td.n a[href]")).next() else {
+                continue;
+            };
+            let Some(mut entry) = entry_from_link(link) else {
+                continue;
+            };
+
+            let cell = |s: &Selector| {
+                row.select(s)
+                    .next()
+                    .map(|c| c.text().collect::<String>().trim().to_string())
+            };
+            entry.size = cell(&size).as_deref().and_then(parse_size);
+            entry.modified = cell(&modified).filter(|m| !m.is_empty());
+            if cell(&kind).as_deref() == Some("Directory") {
+                entry.is_directory = true;
+            }
+            entries.push(entry);
+        }
+        entries
+    }
+}
+
+struct CaddyParser;
+
+impl ListingParser for CaddyParser {
+    fn name(&self) -> &'static str {
+        "Caddy"
+    }
+
+    fn detect(&self, server: Option<&str>, document: &Html) -> bool {
+        server_matches(server, "caddy")
+            || document
+                .select(&selector("td[data-order]"))
+                .next()
+                .is_some()
+    }
+
+    fn parse(&self, document: &Html) -> Vec<ListingEntry> {
+        let mut entries = Vec::new();
+        let size = selector("td[data-order]");
+        let time = selector("time[datetime]");
+
+        for row in document.select(&selector("tr")) {
+            let Some(link) = row.select(&selector("a[href]")).next() else {
+                continue;
+            };
+            let Some(mut entry) = entry_from_link(link) else {
+                continue;
+            };
+
+            // `data-order` holds the exact byte count, -1 for directories.
+            entry.size = row
+                .select(&size)
+                .next()
+                .and_then(|c| c.value().attr("data-order"))
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v >= 0)
+                .map(|v| v as u64);
+            entry.modified = row
+                .select(&time)
+                .next()
+                .and_then(|t| t.value().attr("datetime"))
+                .map(str::to_string);
+            entries.push(entry);
+        }
+        entries
+    }
+}
+
+struct GenericParser;
+
+impl ListingParser for GenericParser {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn detect(&self, _server: Option<&str>, _document: &Html) -> bool {
+        true
+    }
+
+    fn parse(&self, document: &Html) -> Vec<ListingEntry> {
+        document
+            .select(&selector("a[href]"))
+            .filter_map(entry_from_link)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(listing: &Listing) -> Vec<(&str, Option<u64>, bool)> {
+        listing
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.size, e.is_directory))
+            .collect()
+    }
+
+    #[test]
+    fn parses_apache_table() {
+        let body = r#"<html><body><h1>Index of /pub</h1><table>
+<tr><th><a href="?C=N;O=D">Name</a></th><th><a href="?C=M;O=A">Last modified</a></th><th><a href="?C=S;O=A">Size</a></th></tr>
+<tr><td><a href="/">Parent Directory</a></td><td>&nbsp;</td><td>-</td></tr>
+<tr><td><a href="docs/">docs/</a></td><td>2024-01-02 10:00</td><td>-</td></tr>
+<tr><td><a href="My%20File.iso">My File.iso</a></td><td>2024-01-03 11:30</td><td>1.5G</td></tr>
+</table><address>Apache/2.4.57 Server</address></body></html>"#;
+        let listing = parse_listing(None, body);
+        assert_eq!(listing.parser, "Apache");
+        assert_eq!(
+            summary(&listing),
+            [
+                ("docs", None, true),
+                ("My File.iso", Some(1610612736), false)
+            ]
+        );
+        assert_eq!(
+            listing.entries[1].modified.as_deref(),
+            Some("2024-01-03 11:30")
+        );
+    }
+
+    #[test]
+    fn parses_nginx_pre() {
+        let body = "<html><head><title>Index of /files/</title></head><body>
+<h1>Index of /files/</h1><hr><pre><a href=\"../\">../</a>
+<a href=\"iso/\">iso/</a>                                               02-Jan-2024 10:00       -
+<a href=\"notes.txt\">notes.txt</a>                                      03-Jan-2024 11:30     1234
+</pre><hr></body></html>";
+        let listing = parse_listing(Some("nginx/1.24.0"), body);
+        assert_eq!(listing.parser, "nginx");
+        assert_eq!(
+            summary(&listing),
+            [("iso", None, true), ("notes.txt", Some(1234), false)]
+        );
+        assert_eq!(
+            listing.entries[1].modified.as_deref(),
+            Some("03-Jan-2024 11:30")
+        );
+    }
+
+    #[test]
+    fn parses_iis_listing() {
+        let body = "<html><body><H1>files.example.com - /share/</H1><hr><pre>\
+<A HREF=\"/\">[To Parent Directory]</A><br><br> 1/2/2024 10:00 AM        &lt;dir&gt; \
+<A HREF=\"/share/old/\">old</A><br> 1/3/2024 11:30 AM         2048 \
+<A HREF=\"/share/report.pdf\">report.pdf</A><br></pre><hr></body></html>";
+        let listing = parse_listing(Some("Microsoft-IIS/10.0"), body);
+        assert_eq!(listing.parser, "IIS");
+        assert_eq!(
+            summary(&listing),
+            [("old", None, true), ("report.pdf", Some(2048), false)]
+        );
+        assert_eq!(
+            listing.entries[1].modified.as_deref(),
+            Some("1/3/2024 11:30 AM")
+        );
+    }
+
+    #[test]
+    fn parses_lighttpd_listing() {
+        let body = r#"<html><body><div class="list"><table summary="Directory Listing">
+<thead><tr><th class="n">Name</th><th class="m">Last Modified</th><th class="s">Size</th><th class="t">Type</th></tr></thead>
+<tbody><tr class="d"><td class="n"><a href="../">..</a>/</td><td class="m">&nbsp;</td><td class="s">- &nbsp;</td><td class="t">Directory</td></tr>
+<tr class="d"><td class="n"><a href="music/">music</a>/</td><td class="m">2024-Jan-02 10:00:00</td><td class="s">- &nbsp;</td><td class="t">Directory</td></tr>
+<tr><td class="n"><a href="song.flac">song.flac</a></td><td class="m">2024-Jan-03 11:30:00</td><td class="s">4.0M</td><td class="t">audio/flac</td></tr>
+</tbody></table></div></body></html>"#;
+        let listing = parse_listing(None, body);
+        assert_eq!(listing.parser, "lighttpd");
+        assert_eq!(
+            summary(&listing),
+            [("music", None, true), ("song.flac", Some(4194304), false)]
+        );
+    }
+
+    #[test]
+    fn parses_caddy_listing() {
+        let body = r#"<html><body><table><tbody>
+<tr><td><a href="./photos/">photos/</a></td><td data-order="-1">&mdash;</td><td><time datetime="2024-01-02T10:00:00Z">Jan 2</time></td></tr>
+<tr><td><a href="./cat.jpg">cat.jpg</a></td><td data-order="52341">51 KiB</td><td><time datetime="2024-01-03T11:30:00Z">Jan 3</time></td></tr>
+</tbody></table></body></html>"#;
+        let listing = parse_listing(Some("Caddy"), body);
+        assert_eq!(listing.parser, "Caddy");
+        assert_eq!(
+            summary(&listing),
+            [("photos", None, true), ("cat.jpg", Some(52341), false)]
+        );
+        assert_eq!(
+            listing.entries[1].modified.as_deref(),
+            Some("2024-01-03T11:30:00Z")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_generic_links() {
+        let body = r#"<html><head><base href="https://cdn.example.com/mirror/"></head><body>
+<ul><li><a href="?sort=name">Sort</a></li><li><a href="a.zip">a.zip</a></li><li><a href="sub/">sub</a></li></ul>
+</body></html>"#;
+        let listing = parse_listing(None, body);
+        assert_eq!(listing.parser, "generic");
+        assert_eq!(
+            listing.base_href.as_deref(),
+            Some("https://cdn.example.com/mirror/")
+        );
+        assert_eq!(
+            summary(&listing),
+            [("a.zip", None, false), ("sub", None, true)]
+        );
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("12345"), Some(12345));
+        assert_eq!(parse_size("1,024"), Some(1024));
+        assert_eq!(parse_size("1.5K"), Some(1536));
+        assert_eq!(parse_size("4.0 MiB"), Some(4194304));
+        assert_eq!(parse_size("3 GB"), Some(3221225472));
+        assert_eq!(parse_size(" 10 bytes "), Some(10));
+        assert_eq!(parse_size("-"), None);
+        assert_eq!(parse_size("12 parsecs"), None);
+    }
+
+    #[test]
+    fn names_entries_from_hrefs() {
+        assert_eq!(name_from_href("My%20File.txt"), "My File.txt");
+        assert_eq!(name_from_href("/pub/linux/"), "linux");
+        assert_eq!(name_from_href("file.iso?download=1#top"), "file.iso");
+    }
+}
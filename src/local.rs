@@ -0,0 +1,84 @@
+use crate::backend::{Backend, RawListing};
+use crate::listing::ListingEntry;
+use crate::probe::Probe;
+use crate::urls;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use url::Url;
+
+fn to_path(url: &str) -> Result<PathBuf, Box<dyn Error>> {
+    Url::parse(url)?
+        .to_file_path()
+        .map_err(|_| format!("Not a local path: {}", url).into())
+}
+
+/// Walks a directory tree on a local or mounted filesystem. Symlinks are
+/// followed; the crawler's loop detection stops cycles.
+pub struct LocalBackend;
+
+impl Backend for LocalBackend {
+    fn list(&self, url: &str) -> Result<RawListing, Box<dyn Error>> {
+        let path = to_path(url)?;
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(&path)? {
+            let dir_entry = dir_entry?;
+            let name = dir_entry.file_name().to_string_lossy().into_owned();
+            let metadata = match fs::metadata(dir_entry.path()) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    eprintln!("Skipping {}: {}", dir_entry.path().display(), e);
+                    continue;
+                }
+            };
+
+            let is_directory = metadata.is_dir();
+            let mut href = urls::encode_segment(&name);
+            if is_directory {
+                href.push('/');
+            }
+            entries.push(ListingEntry {
+                name,
+                href,
+                size: (!is_directory).then_some(metadata.len()),
+                modified: metadata.modified().ok().map(httpdate::fmt_http_date),
+                content_type: None,
+                etag: None,
+                is_directory,
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!(
+            "Parsed {} entries from {} (local directory)",
+            entries.len(),
+            path.display()
+        );
+        Ok((urls::canonicalize(url, true), entries))
+    }
+
+    fn probe(&self, url: &str) -> Result<Probe, Box<dyn Error>> {
+        Ok(Probe {
+            size: Some(fs::metadata(to_path(url)?)?.len()),
+            content_type: None,
+        })
+    }
+
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(fs::read(to_path(url)?)?)
+    }
+
+    fn fetch_range(
+        &self,
+        url: &str,
+        offset: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut file = File::open(to_path(url)?)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = Vec::with_capacity(length);
+        file.take(length as u64).read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
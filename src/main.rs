@@ -1,13 +1,70 @@
+mod archive;
+mod auth;
+mod backend;
+mod checkpoint;
+mod config;
+mod crawler;
+mod document;
+mod filetypes;
+mod filter;
+mod ftp;
+mod http;
+mod imagemeta;
+mod jsonindex;
+mod listing;
+mod local;
+mod media;
 mod nsfwcheck;
+mod pdf;
+mod probe;
+mod ratelimit;
 mod report;
+mod robots;
+mod s3;
 mod scanner;
+mod scope;
+mod shutdown;
+mod sniff;
+mod urls;
+mod webdav;
 
-use clap::{Arg, Command};
+use auth::{Auth, AuthSettings};
+use backend::{Backend, HttpBackend};
+use checkpoint::{Checkpoint, ScanSettings};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use filetypes::FileTypes;
+use filter::{FilterRules, Filters};
+use ftp::FtpBackend;
+use http::{HttpClient, NetworkOptions, RetryPolicy, USER_AGENT};
+use local::LocalBackend;
 use nsfwcheck::NSFWDetector;
-use reqwest::blocking::Client;
+use ratelimit::RateLimiter;
+use s3::S3Backend;
+use scanner::ScanOptions;
 use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 
+const PARTIAL_REPORT_PATH: &str = "partial_report.md";
+
+fn values(matches: &ArgMatches, id: &str) -> Vec<String> {
+    matches
+        .get_many::<String>(id)
+        .map(|v| v.cloned().collect())
+        .unwrap_or_default()
+}
+
+fn size_arg(matches: &ArgMatches, id: &str) -> Result<Option<u64>, Box<dyn Error>> {
+    match matches.get_one::<String>(id) {
+        Some(value) => match listing::parse_size(value) {
+            Some(size) => Ok(Some(size)),
+            None => Err(format!("Invalid size for --{}: {}", id, value).into()),
+        },
+        None => Ok(None),
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let matches = Command::new("Open Directory Scanner")
         .version("1.0")
@@ -15,8 +72,8 @@ fn main() -> Result<(), Box<dyn Error>> {
         .about("Scans open web directories and creates reports")
         .arg(
             Arg::new("url")
-                .help("The URL of the directory to scan")
-                .required(true)
+                .help("The URL or local path of the directory to scan")
+                .required_unless_present("resume")
                 .index(1),
         )
         .arg(
@@ -31,7 +88,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             Arg::new("output")
                 .short('o')
                 .long("output")
-                .help("Output file path for the report")
+                .help("Output file path for the report (.json for JSON, .csv for one row per file, otherwise markdown)")
                 .value_name("FILE"),
         )
         .arg(
@@ -42,9 +99,286 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .value_name("SECONDS")
                 .default_value("30"),
         )
+        .arg(
+            Arg::new("user")
+                .short('u')
+                .long("user")
+                .help("Credentials for HTTP authentication")
+                .value_name("USER:PASSWORD"),
+        )
+        .arg(
+            Arg::new("digest")
+                .long("digest")
+                .help("Use Digest instead of Basic authentication")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("bearer")
+                .long("bearer")
+                .help("Send this bearer token with every request")
+                .value_name("TOKEN"),
+        )
+        .arg(
+            Arg::new("cookie-jar")
+                .long("cookie-jar")
+                .help("Send cookies from this Netscape cookie file")
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("header")
+                .short('H')
+                .long("header")
+                .help("Extra header to send with every request (repeatable)")
+                .value_name("NAME: VALUE")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("proxy")
+                .long("proxy")
+                .help("Send all requests through this HTTP, HTTPS or SOCKS5 proxy")
+                .value_name("URL"),
+        )
+        .arg(
+            Arg::new("no-proxy")
+                .long("no-proxy")
+                .help("Comma-separated hosts that bypass --proxy")
+                .value_name("HOSTS")
+                .requires("proxy"),
+        )
+        .arg(
+            Arg::new("ca-cert")
+                .long("ca-cert")
+                .help("Also trust the certificates in this PEM file")
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("cert")
+                .long("cert")
+                .help("Client certificate (PEM or PKCS#12)")
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .help("Private key for a PEM client certificate")
+                .value_name("FILE")
+                .requires("cert"),
+        )
+        .arg(
+            Arg::new("cert-password")
+                .long("cert-password")
+                .help("Password for a PKCS#12 client certificate")
+                .value_name("PASSWORD")
+                .requires("cert"),
+        )
+        .arg(
+            Arg::new("insecure")
+                .long("insecure")
+                .help("Do not verify TLS certificates")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("retries")
+                .long("retries")
+                .help("Retries for timeouts, connection errors, 5xx and 429 responses")
+                .value_name("N")
+                .default_value("3"),
+        )
+        .arg(
+            Arg::new("retry-delay")
+                .long("retry-delay")
+                .help("Initial retry delay in milliseconds, doubled on every retry")
+                .value_name("MS")
+                .default_value("500"),
+        )
+        .arg(
+            Arg::new("retry-max-delay")
+                .long("retry-max-delay")
+                .help("Maximum delay between retries in seconds")
+                .value_name("SECONDS")
+                .default_value("30"),
+        )
+        .arg(
+            Arg::new("no-jitter")
+                .long("no-jitter")
+                .help("Do not randomize retry delays")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("rate-limit")
+                .long("rate-limit")
+                .help("Maximum requests per second to each host")
+                .value_name("RPS"),
+        )
+        .arg(
+            Arg::new("delay")
+                .long("delay")
+                .help("Minimum delay between requests to the same host in milliseconds")
+                .value_name("MS")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("respect-robots")
+                .long("respect-robots")
+                .help("Skip paths disallowed by the host's robots.txt")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("s3")
+                .long("s3")
+                .help("Look for an S3 bucket even if the server does not identify as S3")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("concurrency")
+                .short('j')
+                .long("concurrency")
+                .help("Number of directories to fetch in parallel")
+                .value_name("N")
+                .default_value("8"),
+        )
+        .arg(
+            Arg::new("per-host")
+                .long("per-host")
+                .help("Maximum parallel directory fetches per host")
+                .value_name("N")
+                .default_value("4"),
+        )
+        .arg(
+            Arg::new("probe-sizes")
+                .long("probe-sizes")
+                .help("Fetch file sizes and content types via HEAD/Range requests when the listing lacks them")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("sniff")
+                .long("sniff")
+                .help("Identify files by their first bytes (magic numbers) and flag misnamed ones")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("list-archives")
+                .long("list-archives")
+                .help("List the contents of ZIP archives by reading their central directory")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("archive-nsfw")
+                .long("archive-nsfw")
+                .help("Check images inside ZIP archives for NSFW content (implies --list-archives)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("media-metadata")
+                .long("media-metadata")
+                .help("Read duration, codecs and tags of audio and video files via range requests")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("document-metadata")
+                .long("document-metadata")
+                .help("Read author, title, creation tool, page count and dates of PDF and Office documents")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("allow-parent")
+                .long("allow-parent")
+                .help("Follow links outside the starting path on the same host")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("allow-host")
+                .long("allow-host")
+                .help("Additional host to crawl instead of reporting as external (repeatable)")
+                .value_name("HOST")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .help("Only count files matching this glob (repeatable)")
+                .value_name("GLOB")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .help("Skip files and directories matching this glob (repeatable)")
+                .value_name("GLOB")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("include-regex")
+                .long("include-regex")
+                .help("Only count files whose URL path matches this regex (repeatable)")
+                .value_name("REGEX")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("exclude-regex")
+                .long("exclude-regex")
+                .help("Skip files and directories whose URL path matches this regex (repeatable)")
+                .value_name("REGEX")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("include-ext")
+                .long("include-ext")
+                .help("Only count files with this extension (repeatable)")
+                .value_name("EXT")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("exclude-ext")
+                .long("exclude-ext")
+                .help("Skip files with this extension (repeatable)")
+                .value_name("EXT")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("min-size")
+                .long("min-size")
+                .help("Skip files smaller than this size (e.g. 10M)")
+                .value_name("SIZE"),
+        )
+        .arg(
+            Arg::new("max-size")
+                .long("max-size")
+                .help("Skip files larger than this size (e.g. 4G)")
+                .value_name("SIZE"),
+        )
+        .arg(
+            Arg::new("checkpoint")
+                .long("checkpoint")
+                .help("Periodically save the scan state to this file")
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("checkpoint-interval")
+                .long("checkpoint-interval")
+                .help("Seconds between checkpoints")
+                .value_name("SECONDS")
+                .default_value("60"),
+        )
+        .arg(
+            Arg::new("resume")
+                .long("resume")
+                .help("Continue an interrupted scan from a checkpoint file")
+                .value_name("STATE_FILE"),
+        )
         .get_matches();
 
-    let url = matches.get_one::<String>("url").unwrap();
+    let resume = match matches.get_one::<String>("resume") {
+        Some(path) => Some(Checkpoint::load(path)?),
+        None => None,
+    };
+    let url = match &resume {
+        Some(checkpoint) => checkpoint.root.clone(),
+        None => urls::from_input(matches.get_one::<String>("url").unwrap())?,
+    };
+    // Credentials in the URL are moved into the auth settings so that the
+    // URL can be printed and saved as is.
+    let (url, url_credentials) = urls::split_credentials(&url);
     let max_depth = matches
         .get_one::<String>("depth")
         .unwrap()
@@ -55,21 +389,220 @@ fn main() -> Result<(), Box<dyn Error>> {
         .unwrap()
         .parse::<u64>()
         .unwrap_or(30);
+    let retries = matches
+        .get_one::<String>("retries")
+        .unwrap()
+        .parse::<u32>()
+        .unwrap_or(3);
+    let retry_delay = matches
+        .get_one::<String>("retry-delay")
+        .unwrap()
+        .parse::<u64>()
+        .unwrap_or(500);
+    let retry_max_delay = matches
+        .get_one::<String>("retry-max-delay")
+        .unwrap()
+        .parse::<u64>()
+        .unwrap_or(30);
+    let rate_limit = matches
+        .get_one::<String>("rate-limit")
+        .and_then(|v| v.parse::<f64>().ok());
+    let delay = matches
+        .get_one::<String>("delay")
+        .unwrap()
+        .parse::<u64>()
+        .unwrap_or(0);
+    let checkpoint_interval = matches
+        .get_one::<String>("checkpoint-interval")
+        .unwrap()
+        .parse::<u64>()
+        .unwrap_or(60);
+
+    let concurrency = matches
+        .get_one::<String>("concurrency")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap_or(8);
+    let per_host_concurrency = matches
+        .get_one::<String>("per-host")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap_or(4);
 
-    let client = Client::builder()
+    let config = config::load_config()?;
+    let mut rules = FilterRules::from_config(&config);
+    rules.include.extend(values(&matches, "include"));
+    rules.exclude.extend(values(&matches, "exclude"));
+    rules
+        .include_regex
+        .extend(values(&matches, "include-regex"));
+    rules
+        .exclude_regex
+        .extend(values(&matches, "exclude-regex"));
+    rules
+        .include_extensions
+        .extend(values(&matches, "include-ext"));
+    rules
+        .exclude_extensions
+        .extend(values(&matches, "exclude-ext"));
+    if let Some(size) = size_arg(&matches, "min-size")? {
+        rules.min_size = Some(size);
+    }
+    if let Some(size) = size_arg(&matches, "max-size")? {
+        rules.max_size = Some(size);
+    }
+
+    let mut auth = AuthSettings::from_config(&config);
+    if let Some(user) = matches.get_one::<String>("user") {
+        let (username, password) = user.split_once(':').unwrap_or((user, ""));
+        auth.username = Some(username.to_string());
+        auth.password = Some(password.to_string());
+    } else if let Some((username, password)) = url_credentials {
+        auth.username = Some(username);
+        auth.password = Some(password);
+    }
+    if matches.get_flag("digest") {
+        auth.digest = true;
+    }
+    if let Some(token) = matches.get_one::<String>("bearer") {
+        auth.bearer_token = Some(token.clone());
+    }
+    if let Some(path) = matches.get_one::<String>("cookie-jar") {
+        auth.cookie_jar = Some(path.clone());
+    }
+    auth.headers.extend(values(&matches, "header"));
+    auth.host = url::Url::parse(&url)
+        .ok()
+        .and_then(|parsed| urls::host_key(&parsed));
+
+    let settings = ScanSettings {
+        max_depth,
+        probe_sizes: matches.get_flag("probe-sizes"),
+        sniff_types: matches.get_flag("sniff"),
+        list_archives: matches.get_flag("list-archives") || matches.get_flag("archive-nsfw"),
+        check_archive_images: matches.get_flag("archive-nsfw"),
+        media_metadata: matches.get_flag("media-metadata"),
+        document_metadata: matches.get_flag("document-metadata"),
+        allow_parent: matches.get_flag("allow-parent"),
+        allowed_hosts: values(&matches, "allow-host"),
+        filters: rules.clone(),
+        respect_robots: matches.get_flag("respect-robots"),
+        authenticated: auth.username.is_some()
+            || auth.bearer_token.is_some()
+            || auth.cookie_jar.is_some()
+            || !auth.headers.is_empty(),
+    };
+    if let Some(checkpoint) = &resume {
+        checkpoint.check_settings(&settings)?;
+    }
+
+    let options = ScanOptions {
+        max_depth,
+        concurrency,
+        per_host_concurrency,
+        probe_sizes: settings.probe_sizes,
+        sniff_types: settings.sniff_types,
+        list_archives: settings.list_archives,
+        check_archive_images: settings.check_archive_images,
+        media_metadata: settings.media_metadata,
+        document_metadata: settings.document_metadata,
+        allow_parent: settings.allow_parent,
+        allowed_hosts: settings.allowed_hosts.clone(),
+        filters: Filters::new(&rules)?,
+        file_types: FileTypes::from_config(&config)?,
+        settings,
+        // A resumed scan keeps updating its state file unless told otherwise.
+        checkpoint_path: matches
+            .get_one::<String>("checkpoint")
+            .or(matches.get_one::<String>("resume"))
+            .cloned(),
+        checkpoint_interval: Duration::from_secs(checkpoint_interval),
+    };
+
+    let network = NetworkOptions {
+        proxy: matches.get_one::<String>("proxy").cloned(),
+        no_proxy: matches.get_one::<String>("no-proxy").cloned(),
+        ca_bundle: matches.get_one::<String>("ca-cert").cloned(),
+        client_cert: matches.get_one::<String>("cert").cloned(),
+        client_key: matches.get_one::<String>("key").cloned(),
+        cert_password: matches.get_one::<String>("cert-password").cloned(),
+        insecure: matches.get_flag("insecure"),
+    };
+    if network.insecure {
+        eprintln!("Warning: TLS certificate verification is disabled (--insecure)");
+    }
+
+    let client = network
+        .client_builder()?
+        .user_agent(USER_AGENT)
         .timeout(Duration::from_secs(timeout))
         .build()?;
+    let limiter = Arc::new(RateLimiter::new(rate_limit, Duration::from_millis(delay)));
+    let backend: Box<dyn Backend> = if url.starts_with("file://") {
+        Box::new(LocalBackend)
+    } else if url.starts_with("ftp://") {
+        Box::new(FtpBackend::new(
+            auth.username.clone(),
+            auth.password.clone(),
+            Duration::from_secs(timeout),
+            limiter,
+        ))
+    } else {
+        let client = HttpClient::new(
+            client,
+            RetryPolicy {
+                max_attempts: retries + 1,
+                base_delay: Duration::from_millis(retry_delay),
+                max_delay: Duration::from_secs(retry_max_delay),
+                jitter: !matches.get_flag("no-jitter"),
+            },
+            limiter,
+            Auth::new(&auth)?,
+        );
+        let http = HttpBackend::new(client, matches.get_flag("respect-robots"));
+        match s3::detect_bucket(&url, &http, matches.get_flag("s3")) {
+            Some(bucket_url) => {
+                println!("Detected S3 bucket at {}", bucket_url);
+                Box::new(S3Backend::new(http, bucket_url))
+            }
+            None => Box::new(http),
+        }
+    };
 
-    let nsfw_detector = NSFWDetector::new()?;
+    let model_client = network.client_builder()?.user_agent(USER_AGENT).build()?;
+    let nsfw_detector = NSFWDetector::new(&model_client)?;
 
-    println!("Starting scan of {} with max depth {}", url, max_depth);
-    let (report, _) = scanner::scan_directory(url, 0, max_depth, &client, &nsfw_detector)?;
+    shutdown::install_handler()?;
+    match &resume {
+        Some(checkpoint) => println!(
+            "Resuming scan of {} with {} directories left",
+            url,
+            checkpoint.pending.len()
+        ),
+        None => println!("Starting scan of {} with max depth {}", url, max_depth),
+    }
+    let (report, files) =
+        scanner::scan_directory(&url, &options, backend.as_ref(), &nsfw_detector, resume)?;
 
-    println!("\n=== Scan Complete ===");
+    if report.incomplete {
+        println!("\n=== Scan Interrupted ===");
+    } else {
+        println!("\n=== Scan Complete ===");
+    }
     report.print();
 
-    if let Some(output_path) = matches.get_one::<String>("output") {
-        report.save_to_file(output_path)?;
+    let output_path = match matches.get_one::<String>("output") {
+        Some(path) => Some(path.as_str()),
+        // An interrupted scan is always saved so its results are not lost.
+        None if report.incomplete => Some(PARTIAL_REPORT_PATH),
+        None => None,
+    };
+    if let Some(output_path) = output_path {
+        match Path::new(output_path).extension().and_then(|e| e.to_str()) {
+            Some("json") => report.save_json(&files, output_path)?,
+            Some("csv") => report::save_csv(&files, output_path)?,
+            _ => report.save_to_file(output_path)?,
+        }
         println!("\nReport saved to: {}", output_path);
     }
 
@@ -0,0 +1,917 @@
+use crate::backend::Backend;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Bytes fetched from the start of a media file. Enough for the headers of
+/// Matroska, MP3, FLAC, Ogg and WAV files; MP4 boxes are read separately.
+const MEDIA_PREFIX: usize = 65_536;
+
+/// Bytes fetched from the end of an Ogg file to find its last page.
+const OGG_TAIL: u64 = 65_536;
+
+/// `moov` boxes larger than this are not downloaded.
+const MAX_MOOV: u64 = 32 * 1_048_576;
+
+/// Top-level MP4 boxes skipped before giving up on finding `moov`.
+const MAX_BOXES: usize = 64;
+
+/// Duration, codecs and tags of an audio or video file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MediaMetadata {
+    /// Duration in seconds.
+    pub duration: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    /// Overall bitrate in bits per second.
+    pub bitrate: Option<u64>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+impl MediaMetadata {
+    pub fn codecs(&self) -> impl Iterator<Item = &String> {
+        self.video_codec.iter().chain(self.audio_codec.iter())
+    }
+}
+
+/// A remote file read piecewise, with its first bytes fetched up front.
+struct Source<'a> {
+    url: &'a str,
+    backend: &'a dyn Backend,
+    size: Option<u64>,
+    prefix: Vec<u8>,
+}
+
+impl Source<'_> {
+    /// Reads up to `length` bytes at `offset`; fewer at the end of the file.
+    fn read(&self, offset: u64, length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let start = offset as usize;
+        let end = start.saturating_add(length);
+        if let Some(bytes) = self.prefix.get(start..end) {
+            return Ok(bytes.to_vec());
+        }
+        if self.prefix.len() < MEDIA_PREFIX {
+            // The prefix is the whole file.
+            return Ok(self.prefix.get(start..).unwrap_or_default().to_vec());
+        }
+        self.backend.fetch_range(self.url, offset, length)
+    }
+}
+
+fn u16_be(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_be(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_be(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn u16_le(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_le(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn text(bytes: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim_matches(char::from(0)).trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+// MP4 and QuickTime
+
+/// The boxes in `data`, as type and body.
+fn boxes(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some(size) = u32_be(data, pos) {
+        let (header, size) = match size {
+            1 => match u64_be(data, pos + 8) {
+                Some(size) => (16, size as usize),
+                None => break,
+            },
+            0 => (8, data.len() - pos),
+            size => (8, size as usize),
+        };
+        let Some(end) = pos.checked_add(size).filter(|&end| end >= pos + header) else {
+            break;
+        };
+        let (Some(kind), Some(body)) = (data.get(pos + 4..pos + 8), data.get(pos + header..end))
+        else {
+            break;
+        };
+        found.push((kind, body));
+        pos = end;
+    }
+    found
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    boxes(data)
+        .into_iter()
+        .find(|(k, _)| *k == kind)
+        .map(|(_, body)| body)
+}
+
+fn path<'a>(data: &'a [u8], kinds: &[&[u8]]) -> Option<&'a [u8]> {
+    kinds.iter().try_fold(data, |data, kind| child(data, kind))
+}
+
+fn mp4_codec(fourcc: &[u8]) -> String {
+    match fourcc {
+        b"avc1" | b"avc3" => "H.264",
+        b"hvc1" | b"hev1" => "H.265",
+        b"av01" => "AV1",
+        b"vp08" => "VP8",
+        b"vp09" => "VP9",
+        b"mp4v" => "MPEG-4 Visual",
+        b"jpeg" => "Motion JPEG",
+        b"apcn" | b"apch" | b"apcs" | b"apco" | b"ap4h" => "ProRes",
+        b"mp4a" => "AAC",
+        b"ac-3" => "AC-3",
+        b"ec-3" => "E-AC-3",
+        b"Opus" => "Opus",
+        b"fLaC" => "FLAC",
+        b"alac" => "ALAC",
+        b"lpcm" | b"sowt" | b"twos" | b"in24" | b"fl32" => "PCM",
+        b".mp3" => "MP3",
+        other => return String::from_utf8_lossy(other).trim().to_string(),
+    }
+    .to_string()
+}
+
+/// Reads a `data` box of an iTunes-style metadata item.
+fn ilst_text(ilst: &[u8], kind: &[u8]) -> Option<String> {
+    text(child(child(ilst, kind)?, b"data")?.get(8..)?)
+}
+
+fn parse_moov(moov: &[u8]) -> MediaMetadata {
+    let mut metadata = MediaMetadata::default();
+    if let Some(mvhd) = child(moov, b"mvhd") {
+        let (timescale, duration) = match mvhd.first() {
+            Some(1) => (u32_be(mvhd, 20), u64_be(mvhd, 24)),
+            _ => (u32_be(mvhd, 12), u32_be(mvhd, 16).map(u64::from)),
+        };
+        if let (Some(timescale), Some(duration)) = (timescale, duration)
+            && timescale > 0
+        {
+            metadata.duration = Some(duration as f64 / timescale as f64);
+        }
+    }
+
+    for (kind, trak) in boxes(moov) {
+        if kind != b"trak" {
+            continue;
+        }
+        let handler = path(trak, &[b"mdia", b"hdlr"]).and_then(|hdlr| hdlr.get(8..12));
+        let codec = path(trak, &[b"mdia", b"minf", b"stbl", b"stsd"])
+            .and_then(|stsd| stsd.get(12..16))
+            .map(mp4_codec);
+        match handler {
+            Some(b"vide") if metadata.video_codec.is_none() => {
+                metadata.video_codec = codec;
+                // Width and height end the track header, as 16.16 fixed point.
+                if let Some(tkhd) = child(trak, b"tkhd") {
+                    let end = tkhd.len();
+                    metadata.width = end
+                        .checked_sub(8)
+                        .and_then(|o| u16_be(tkhd, o))
+                        .map(u32::from);
+                    metadata.height = end
+                        .checked_sub(4)
+                        .and_then(|o| u16_be(tkhd, o))
+                        .map(u32::from);
+                }
+            }
+            Some(b"soun") if metadata.audio_codec.is_none() => metadata.audio_codec = codec,
+            _ => {}
+        }
+    }
+
+    // MP4 `meta` is a full box with a version; QuickTime's is not.
+    if let Some(meta) = path(moov, &[b"udta", b"meta"]) {
+        let children = match meta.get(4..8) {
+            Some(b"hdlr") => meta,
+            _ => meta.get(4..).unwrap_or_default(),
+        };
+        if let Some(ilst) = child(children, b"ilst") {
+            metadata.title = ilst_text(ilst, b"\xA9nam");
+            metadata.artist = ilst_text(ilst, b"\xA9ART");
+        }
+    }
+    metadata
+}
+
+/// Walks the top-level boxes to the `moov` box, which may follow the
+/// media data at the end of the file.
+fn read_mp4(source: &Source) -> Result<Option<MediaMetadata>, Box<dyn Error>> {
+    let mut offset: u64 = 0;
+    for _ in 0..MAX_BOXES {
+        let header = source.read(offset, 16)?;
+        let (Some(size), Some(kind)) = (u32_be(&header, 0), header.get(4..8)) else {
+            return Ok(None);
+        };
+        let (header_length, size) = match size {
+            1 => (16, u64_be(&header, 8).ok_or("truncated box header")?),
+            // A size of 0 extends to the end of the file, so only a `moov`
+            // box is worth reading.
+            0 if kind == b"moov" => (8, MAX_MOOV),
+            0 => return Ok(None),
+            size => (8, size as u64),
+        };
+        if size < header_length {
+            return Err(format!("invalid box size {} at offset {}", size, offset).into());
+        }
+        if kind == b"moov" {
+            if size > MAX_MOOV {
+                return Err(format!("moov box of {} bytes is too large", size).into());
+            }
+            let moov = source.read(offset + header_length, (size - header_length) as usize)?;
+            return Ok(Some(parse_moov(&moov)));
+        }
+        match offset.checked_add(size) {
+            Some(next) => offset = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+// Matroska and WebM
+
+/// Reads an EBML variable-length integer, returning it and its length.
+/// IDs keep their length marker, sizes do not.
+fn vint(data: &[u8], pos: usize, keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *data.get(pos)?;
+    let length = first.leading_zeros() as usize + 1;
+    if length > 8 {
+        return None;
+    }
+    let mut value = match keep_marker {
+        true => first as u64,
+        false => (first as u64) & (0xFF >> length),
+    };
+    for byte in data.get(pos + 1..pos + length)? {
+        value = (value << 8) | *byte as u64;
+    }
+    Some((value, length))
+}
+
+/// The elements in `data`, as ID and body. Elements of unknown size run
+/// to the end of `data`.
+fn elements(data: &[u8]) -> Vec<(u64, &[u8])> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some((id, id_length)) = vint(data, pos, true) {
+        let Some((size, size_length)) = vint(data, pos + id_length, false) else {
+            break;
+        };
+        let start = pos + id_length + size_length;
+        let unknown = size == (1 << (7 * size_length)) - 1;
+        let end = match unknown {
+            true => data.len(),
+            false => start.saturating_add(size as usize).min(data.len()),
+        };
+        found.push((id, data.get(start..end).unwrap_or_default()));
+        pos = end;
+    }
+    found
+}
+
+fn ebml_uint(data: &[u8]) -> u64 {
+    data.iter()
+        .fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn ebml_float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn matroska_codec(id: &str) -> String {
+    let name = match id {
+        "V_MPEG4/ISO/AVC" => "H.264",
+        "V_MPEGH/ISO/HEVC" => "H.265",
+        "V_AV1" => "AV1",
+        "V_VP8" => "VP8",
+        "V_VP9" => "VP9",
+        "V_THEORA" => "Theora",
+        "A_OPUS" => "Opus",
+        "A_VORBIS" => "Vorbis",
+        "A_FLAC" => "FLAC",
+        "A_AC3" => "AC-3",
+        "A_EAC3" => "E-AC-3",
+        "A_DTS" => "DTS",
+        "A_MPEG/L3" => "MP3",
+        id if id.starts_with("A_AAC") => "AAC",
+        id if id.starts_with("A_PCM") => "PCM",
+        id => id,
+    };
+    name.to_string()
+}
+
+const EBML_SEGMENT: u64 = 0x1853_8067;
+const EBML_INFO: u64 = 0x1549_A966;
+const EBML_TIMESTAMP_SCALE: u64 = 0x2A_D7B1;
+const EBML_DURATION: u64 = 0x4489;
+const EBML_TITLE: u64 = 0x7BA9;
+const EBML_TRACKS: u64 = 0x1654_AE6B;
+const EBML_TRACK_ENTRY: u64 = 0xAE;
+const EBML_TRACK_TYPE: u64 = 0x83;
+const EBML_CODEC_ID: u64 = 0x86;
+const EBML_VIDEO: u64 = 0xE0;
+const EBML_PIXEL_WIDTH: u64 = 0xB0;
+const EBML_PIXEL_HEIGHT: u64 = 0xBA;
+
+/// Reads the Info and Tracks elements at the start of the Segment.
+fn parse_matroska(data: &[u8]) -> MediaMetadata {
+    let mut metadata = MediaMetadata::default();
+    let Some((_, segment)) = elements(data)
+        .into_iter()
+        .find(|(id, _)| *id == EBML_SEGMENT)
+    else {
+        return metadata;
+    };
+
+    for (id, body) in elements(segment) {
+        match id {
+            EBML_INFO => {
+                let mut scale = 1_000_000;
+                let mut duration = None;
+                for (id, value) in elements(body) {
+                    match id {
+                        EBML_TIMESTAMP_SCALE => scale = ebml_uint(value),
+                        EBML_DURATION => duration = ebml_float(value),
+                        EBML_TITLE => metadata.title = text(value),
+                        _ => {}
+                    }
+                }
+                metadata.duration = duration.map(|d| d * scale as f64 / 1e9);
+            }
+            EBML_TRACKS => {
+                for (_, entry) in elements(body)
+                    .into_iter()
+                    .filter(|(id, _)| *id == EBML_TRACK_ENTRY)
+                {
+                    let fields = elements(entry);
+                    let field =
+                        |wanted| fields.iter().find(|(id, _)| *id == wanted).map(|(_, v)| *v);
+                    let codec = field(EBML_CODEC_ID)
+                        .and_then(text)
+                        .map(|id| matroska_codec(&id));
+                    match field(EBML_TRACK_TYPE).map(ebml_uint) {
+                        Some(1) if metadata.video_codec.is_none() => {
+                            metadata.video_codec = codec;
+                            let video = elements(field(EBML_VIDEO).unwrap_or_default());
+                            for (id, value) in video {
+                                match id {
+                                    EBML_PIXEL_WIDTH => {
+                                        metadata.width = Some(ebml_uint(value) as u32)
+                                    }
+                                    EBML_PIXEL_HEIGHT => {
+                                        metadata.height = Some(ebml_uint(value) as u32)
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        }
+                        Some(2) if metadata.audio_codec.is_none() => metadata.audio_codec = codec,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    metadata
+}
+
+// MP3
+
+/// Decodes an ID3v2 text frame: an encoding byte followed by the text.
+fn id3_text(frame: &[u8]) -> Option<String> {
+    let (encoding, body) = frame.split_first()?;
+    match encoding {
+        1 | 2 => {
+            let big_endian = *encoding == 2 || body.starts_with(b"\xFE\xFF");
+            let units: Vec<u16> = body
+                .chunks_exact(2)
+                .map(|c| match big_endian {
+                    true => u16::from_be_bytes([c[0], c[1]]),
+                    false => u16::from_le_bytes([c[0], c[1]]),
+                })
+                .filter(|u| *u != 0xFEFF)
+                .collect();
+            text(String::from_utf16_lossy(&units).as_bytes())
+        }
+        3 => text(body),
+        _ => text(
+            &body
+                .iter()
+                .map(|b| *b as char)
+                .collect::<String>()
+                .into_bytes(),
+        ),
+    }
+}
+
+fn syncsafe(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(
+        bytes
+            .iter()
+            .fold(0, |value, byte| (value << 7) | (*byte & 0x7F) as u32),
+    )
+}
+
+/// Reads title and artist from an ID3v2 tag, returning its total length.
+fn parse_id3(data: &[u8], metadata: &mut MediaMetadata) -> Option<u64> {
+    let version = *data.get(3)?;
+    let footer = data.get(5)? & 0x10 != 0;
+    let size = syncsafe(data, 6)? as usize;
+    let tag = data.get(10..(10 + size).min(data.len()))?;
+
+    let mut pos = 0;
+    while pos + 10 <= tag.len() {
+        let (id, length, header) = match version {
+            2 => (
+                tag.get(pos..pos + 3)?,
+                u32_be(&[&[0], tag.get(pos + 3..pos + 6)?].concat(), 0)? as usize,
+                6,
+            ),
+            3 => (tag.get(pos..pos + 4)?, u32_be(tag, pos + 4)? as usize, 10),
+            _ => (tag.get(pos..pos + 4)?, syncsafe(tag, pos + 4)? as usize, 10),
+        };
+        if id[0] == 0 {
+            break;
+        }
+        let Some(body) = tag.get(pos + header..pos + header + length) else {
+            break;
+        };
+        match id {
+            b"TIT2" | b"TT2" => metadata.title = id3_text(body),
+            b"TPE1" | b"TP1" => metadata.artist = id3_text(body),
+            _ => {}
+        }
+        pos += header + length;
+    }
+    Some(10 + size as u64 + if footer { 10 } else { 0 })
+}
+
+/// Reads the first MPEG audio frame at `data`: codec, bitrate and, from a
+/// Xing/Info header, the number of frames.
+fn parse_mpeg_frame(data: &[u8], audio_size: Option<u64>, metadata: &mut MediaMetadata) {
+    let Some(pos) = data
+        .windows(2)
+        .position(|w| w[0] == 0xFF && w[1] & 0xE6 == 0xE2)
+    else {
+        return;
+    };
+    let header = &data[pos..];
+    let (Some(b1), Some(b2), Some(b3)) = (header.get(1), header.get(2), header.get(3)) else {
+        return;
+    };
+    let mpeg1 = (b1 >> 3) & 0x03 == 3;
+    let bitrates: [u64; 15] = match mpeg1 {
+        true => [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+        false => [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    };
+    let sample_rates = match (b1 >> 3) & 0x03 {
+        3 => [44_100, 48_000, 32_000],
+        2 => [22_050, 24_000, 16_000],
+        _ => [11_025, 12_000, 8_000],
+    };
+    let (Some(bitrate), Some(sample_rate)) = (
+        bitrates.get((b2 >> 4) as usize),
+        sample_rates.get(((b2 >> 2) & 0x03) as usize),
+    ) else {
+        return;
+    };
+    metadata.audio_codec = Some("MP3".to_string());
+
+    let mono = b3 >> 6 == 3;
+    let side_info = match (mpeg1, mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let samples_per_frame = if mpeg1 { 1152.0 } else { 576.0 };
+    let xing = header.get(4 + side_info..);
+    let frames = xing
+        .filter(|x| x.starts_with(b"Xing") || x.starts_with(b"Info"))
+        .filter(|x| u32_be(x, 4).is_some_and(|flags| flags & 1 != 0))
+        .and_then(|x| u32_be(x, 8));
+    match frames {
+        Some(frames) => {
+            metadata.duration = Some(frames as f64 * samples_per_frame / *sample_rate as f64)
+        }
+        // Constant bitrate: the duration follows from the size.
+        None if *bitrate > 0 => {
+            metadata.bitrate = Some(bitrate * 1000);
+            metadata.duration = audio_size.map(|size| size as f64 * 8.0 / (bitrate * 1000) as f64);
+        }
+        None => {}
+    }
+}
+
+fn read_mp3(source: &Source) -> Result<MediaMetadata, Box<dyn Error>> {
+    let mut metadata = MediaMetadata::default();
+    let mut audio_start = 0;
+    if source.prefix.starts_with(b"ID3") {
+        audio_start = parse_id3(&source.prefix, &mut metadata).unwrap_or(0);
+    }
+    let frame = source.read(audio_start, 4096)?;
+    let audio_size = source.size.map(|size| size.saturating_sub(audio_start));
+    parse_mpeg_frame(&frame, audio_size, &mut metadata);
+    Ok(metadata)
+}
+
+// FLAC, Ogg Vorbis and Opus
+
+/// Reads `TITLE` and `ARTIST` from a Vorbis comment block: a vendor
+/// string and a list of `KEY=value` strings, lengths little-endian.
+fn parse_vorbis_comment(data: &[u8], metadata: &mut MediaMetadata) -> Option<()> {
+    let vendor_length = u32_le(data, 0)? as usize;
+    let mut pos = 4 + vendor_length;
+    let count = u32_le(data, pos)?;
+    pos += 4;
+    for _ in 0..count {
+        let length = u32_le(data, pos)? as usize;
+        let comment = String::from_utf8_lossy(data.get(pos + 4..pos + 4 + length)?);
+        if let Some((key, value)) = comment.split_once('=') {
+            match key.to_ascii_uppercase().as_str() {
+                "TITLE" => metadata.title = text(value.as_bytes()),
+                "ARTIST" => metadata.artist = text(value.as_bytes()),
+                _ => {}
+            }
+        }
+        pos += 4 + length;
+    }
+    Some(())
+}
+
+/// Reads STREAMINFO and the Vorbis comment from the metadata blocks that
+/// follow the `fLaC` marker. Pictures are skipped without downloading.
+fn read_flac(source: &Source) -> Result<MediaMetadata, Box<dyn Error>> {
+    let mut metadata = MediaMetadata {
+        audio_codec: Some("FLAC".to_string()),
+        ..Default::default()
+    };
+    let mut offset = 4;
+    for _ in 0..64 {
+        let header = source.read(offset, 4)?;
+        let Some(&kind) = header.first() else {
+            break;
+        };
+        let length = u32_be(&[&[0], header.get(1..4).unwrap_or_default()].concat(), 0)
+            .ok_or("truncated FLAC metadata block")? as usize;
+        match kind & 0x7F {
+            0 => {
+                let info = source.read(offset + 4, length)?;
+                if let Some(fields) = info.get(10..18) {
+                    let sample_rate = (fields[0] as u64) << 12
+                        | (fields[1] as u64) << 4
+                        | (fields[2] as u64) >> 4;
+                    let samples =
+                        ((fields[3] & 0x0F) as u64) << 32 | u32_be(fields, 4).unwrap_or(0) as u64;
+                    if sample_rate > 0 && samples > 0 {
+                        metadata.duration = Some(samples as f64 / sample_rate as f64);
+                    }
+                }
+            }
+            4 => {
+                let comment = source.read(offset + 4, length)?;
+                parse_vorbis_comment(&comment, &mut metadata);
+            }
+            _ => {}
+        }
+        if kind & 0x80 != 0 {
+            break;
+        }
+        offset += 4 + length as u64;
+    }
+    Ok(metadata)
+}
+
+/// Reads the codec and comments from the first Ogg packets, and the
+/// duration from the granule position of the last page.
+fn read_ogg(source: &Source) -> Result<MediaMetadata, Box<dyn Error>> {
+    let mut metadata = MediaMetadata::default();
+    let data = &source.prefix;
+    let find = |needle: &[u8]| data.windows(needle.len()).position(|w| w == needle);
+
+    let (sample_rate, pre_skip) = if let Some(pos) = find(b"\x01vorbis") {
+        metadata.audio_codec = Some("Vorbis".to_string());
+        if let Some(comment) = find(b"\x03vorbis") {
+            parse_vorbis_comment(&data[comment + 7..], &mut metadata);
+        }
+        (u32_le(data, pos + 12).unwrap_or(0) as u64, 0)
+    } else if let Some(pos) = find(b"OpusHead") {
+        metadata.audio_codec = Some("Opus".to_string());
+        if let Some(comment) = find(b"OpusTags") {
+            parse_vorbis_comment(&data[comment + 8..], &mut metadata);
+        }
+        // Opus granule positions always count 48 kHz samples.
+        (48_000, u16_le(data, pos + 10).unwrap_or(0) as u64)
+    } else {
+        return Ok(metadata);
+    };
+
+    if sample_rate > 0 {
+        let tail = match data.len() < MEDIA_PREFIX {
+            true => data.clone(),
+            false => source.backend.fetch_tail(source.url, OGG_TAIL)?.1,
+        };
+        let last_page = tail.windows(4).rposition(|w| w == b"OggS");
+        if let Some(granule) = last_page.and_then(|pos| u64_le(&tail, pos + 6)) {
+            metadata.duration = Some(granule.saturating_sub(pre_skip) as f64 / sample_rate as f64);
+        }
+    }
+    Ok(metadata)
+}
+
+// WAV
+
+fn parse_wav(data: &[u8]) -> MediaMetadata {
+    let mut metadata = MediaMetadata::default();
+    let mut byte_rate = 0;
+    let mut pos = 12;
+    while let (Some(kind), Some(length)) = (data.get(pos..pos + 4), u32_le(data, pos + 4)) {
+        match kind {
+            b"fmt " => {
+                let codec = match u16_le(data, pos + 8) {
+                    Some(1 | 0xFFFE) => "PCM",
+                    Some(3) => "PCM (float)",
+                    Some(0x55) => "MP3",
+                    _ => "WAV",
+                };
+                metadata.audio_codec = Some(codec.to_string());
+                byte_rate = u32_le(data, pos + 16).unwrap_or(0) as u64;
+                metadata.bitrate = Some(byte_rate * 8).filter(|b| *b > 0);
+            }
+            b"data" => {
+                if byte_rate > 0 {
+                    metadata.duration = Some(length as f64 / byte_rate as f64);
+                }
+                break;
+            }
+            _ => {}
+        }
+        pos += 8 + length as usize + length as usize % 2;
+    }
+    metadata
+}
+
+/// Reads the duration, codecs and tags of an audio or video file from its
+/// headers, using range requests. Returns `None` for unsupported formats.
+pub fn read(
+    url: &str,
+    size: Option<u64>,
+    backend: &dyn Backend,
+) -> Result<Option<MediaMetadata>, Box<dyn Error>> {
+    let source = Source {
+        url,
+        backend,
+        size,
+        prefix: backend.fetch_prefix(url, MEDIA_PREFIX)?,
+    };
+    let data = &source.prefix;
+    let at = |offset: usize, signature: &[u8]| {
+        data.get(offset..offset + signature.len()) == Some(signature)
+    };
+
+    let mut metadata = if at(4, b"ftyp") || at(4, b"moov") || at(4, b"wide") || at(4, b"mdat") {
+        match read_mp4(&source)? {
+            Some(metadata) => metadata,
+            None => return Ok(None),
+        }
+    } else if at(0, b"\x1A\x45\xDF\xA3") {
+        parse_matroska(data)
+    } else if at(0, b"fLaC") {
+        read_flac(&source)?
+    } else if at(0, b"OggS") {
+        read_ogg(&source)?
+    } else if at(0, b"RIFF") && at(8, b"WAVE") {
+        parse_wav(data)
+    } else if at(0, b"ID3") || (data.len() > 1 && data[0] == 0xFF && data[1] & 0xE6 == 0xE2) {
+        read_mp3(&source)?
+    } else {
+        return Ok(None);
+    };
+
+    if metadata.bitrate.is_none()
+        && let (Some(size), Some(duration)) = (size, metadata.duration)
+        && duration > 0.0
+    {
+        metadata.bitrate = Some((size as f64 * 8.0 / duration) as u64);
+    }
+    Ok(Some(metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryBackend;
+
+    fn mp4_box(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        [&(body.len() as u32 + 8).to_be_bytes()[..], kind, body].concat()
+    }
+
+    fn ftyp() -> Vec<u8> {
+        mp4_box(b"ftyp", b"isom\0\0\0\0")
+    }
+
+    fn read_bytes(
+        data: Vec<u8>,
+        known_size: bool,
+    ) -> Result<Option<MediaMetadata>, Box<dyn Error>> {
+        let size = known_size.then_some(data.len() as u64);
+        read("memory:///file", size, &MemoryBackend(data))
+    }
+
+    #[test]
+    fn reads_mp4_duration() {
+        let mut mvhd = vec![0; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&5000u32.to_be_bytes());
+        let data = [ftyp(), mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd))].concat();
+        let metadata = read_bytes(data, true).unwrap().unwrap();
+        assert_eq!(metadata.duration, Some(5.0));
+    }
+
+    #[test]
+    fn reads_mp4_with_moov_at_end() {
+        let mut mvhd = vec![0; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&2500u32.to_be_bytes());
+        let data = [
+            ftyp(),
+            mp4_box(b"mdat", &vec![0; 2 * MEDIA_PREFIX]),
+            mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd)),
+        ]
+        .concat();
+        let metadata = read_bytes(data, false).unwrap().unwrap();
+        assert_eq!(metadata.duration, Some(2.5));
+    }
+
+    #[test]
+    fn stops_at_truncated_mp4_box_header() {
+        let data = [ftyp(), vec![0, 0, 0, 9, b'm']].concat();
+        assert!(read_bytes(data.clone(), false).unwrap().is_none());
+        assert!(read_bytes(data, true).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_undersized_mp4_box() {
+        let data = [ftyp(), vec![0, 0, 0, 4], b"free".to_vec()].concat();
+        assert!(read_bytes(data, false).is_err());
+    }
+
+    #[test]
+    fn stops_at_mp4_box_running_to_end() {
+        let data = [ftyp(), vec![0, 0, 0, 0], b"mdat".to_vec(), vec![0; 32]].concat();
+        assert!(read_bytes(data.clone(), false).unwrap().is_none());
+        assert!(read_bytes(data, true).unwrap().is_none());
+    }
+
+    #[test]
+    fn stops_at_overflowing_mp4_box_size() {
+        let large = [
+            vec![0, 0, 0, 1],
+            b"free".to_vec(),
+            u64::MAX.to_be_bytes().to_vec(),
+        ];
+        let data = [ftyp(), large.concat()].concat();
+        assert!(read_bytes(data, false).unwrap().is_none());
+    }
+
+    #[test]
+    fn skips_nested_boxes_with_invalid_sizes() {
+        assert!(boxes(b"\0\0\0\x02free").is_empty());
+        let large = [b"\0\0\0\x01free".to_vec(), u64::MAX.to_be_bytes().to_vec()].concat();
+        assert!(boxes(&large).is_empty());
+        assert!(parse_moov(&large).duration.is_none());
+    }
+
+    #[test]
+    fn reads_truncated_matroska() {
+        let data = [
+            b"\x1A\x45\xDF\xA3\x80".to_vec(),
+            b"\x18\x53\x80\x67\x01\xFF\xFF\xFF\xFF\xFF\xFF\xFF".to_vec(),
+            b"\x15\x49\xA9\x66\x08\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x44".to_vec(),
+        ]
+        .concat();
+        let metadata = read_bytes(data, false).unwrap().unwrap();
+        assert!(metadata.duration.is_none());
+    }
+
+    #[test]
+    fn reads_truncated_id3_tag() {
+        let data = b"ID3\x04\x00\x00\x7F\x7F\x7F\x7FTIT2\x7F\x7F\x7F\x7F\x00\x00\x03ab".to_vec();
+        let metadata = read_bytes(data, true).unwrap().unwrap();
+        assert!(metadata.title.is_none());
+        assert!(metadata.audio_codec.is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_flac() {
+        assert!(read_bytes(b"fLaC\x00\x00\x00".to_vec(), true).is_err());
+    }
+
+    fn ogg_page(granule: u64, body: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend(granule.to_le_bytes());
+        page.extend([0; 12]);
+        page.extend(body);
+        page
+    }
+
+    fn vorbis_comment(vendor_length: u32, comments: &[&str]) -> Vec<u8> {
+        let mut comment = b"\x03vorbis".to_vec();
+        comment.extend(vendor_length.to_le_bytes());
+        comment.extend(b"abc");
+        comment.extend((comments.len() as u32).to_le_bytes());
+        for text in comments {
+            comment.extend((text.len() as u32).to_le_bytes());
+            comment.extend(text.as_bytes());
+        }
+        comment
+    }
+
+    fn vorbis_file(comment: Vec<u8>) -> Vec<u8> {
+        let mut identification = b"\x01vorbis\0\0\0\0\x02".to_vec();
+        identification.extend(44_100u32.to_le_bytes());
+        [
+            ogg_page(0, &identification),
+            ogg_page(0, &comment),
+            ogg_page(441_000, &[0; 32]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn reads_ogg_vorbis_duration_and_tags() {
+        let data = vorbis_file(vorbis_comment(3, &["title=Song", "ARTIST=Band"]));
+        let metadata = read_bytes(data, false).unwrap().unwrap();
+        assert_eq!(metadata.audio_codec.as_deref(), Some("Vorbis"));
+        assert_eq!(metadata.duration, Some(10.0));
+        assert_eq!(metadata.title.as_deref(), Some("Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Band"));
+    }
+
+    #[test]
+    fn ignores_overlong_vorbis_comments() {
+        let data = vorbis_file(vorbis_comment(u32::MAX, &["TITLE=Song"]));
+        let metadata = read_bytes(data, false).unwrap().unwrap();
+        assert!(metadata.title.is_none());
+        assert_eq!(metadata.duration, Some(10.0));
+
+        let mut comment = vorbis_comment(3, &[]);
+        comment.truncate(comment.len() - 4);
+        comment.extend(u32::MAX.to_le_bytes());
+        comment.extend(u32::MAX.to_le_bytes());
+        let metadata = read_bytes(vorbis_file(comment), false).unwrap().unwrap();
+        assert!(metadata.title.is_none());
+    }
+
+    #[test]
+    fn reads_truncated_wav() {
+        let mut data = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x02\0".to_vec();
+        data.extend(44_100u32.to_le_bytes());
+        data.extend(176_400u32.to_le_bytes());
+        data.extend(b"\x04\0\x10\0LIST\xFF\xFF\xFF\xFF");
+        let metadata = read_bytes(data, true).unwrap().unwrap();
+        assert_eq!(metadata.audio_codec.as_deref(), Some("PCM"));
+        assert_eq!(metadata.bitrate, Some(1_411_200));
+        assert!(metadata.duration.is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config;
 use image::ImageReader;
 use nsfw::{create_model, examine};
 use reqwest::blocking::Client;
@@ -9,31 +10,7 @@ use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 use toml::{Table, Value};
 
-const CONFIG_PATH: &str = "resources/config.toml";
 const GITHUB_API_RELEASES: &str = "https://api.github.com/repos/Fyko/nsfw/releases/latest";
-const DEFAULT_CONFIG: &str = r#"# Open Directory Scanner Configuration
-
-# Model information
-[model]
-version = "0.0.0"  # Will be updated with the actual version from GitHub
-last_updated = ""  # Will be populated when model is downloaded
-url = "https://github.com/Fyko/nsfw/releases/latest/download/model.onnx"
-path = "resources/model.onnx"
-
-# NSFW detection thresholds
-[thresholds]
-porn = 0.5    # Porn classification threshold
-hentai = 0.6  # Hentai classification threshold
-sexy = 0.8    # Sexy classification threshold
-
-# Scanner defaults
-[scanner]
-default_depth = 3
-default_timeout = 30
-
-# Report settings
-[report]
-include_nsfw_urls = true  # Whether to include URLs of NSFW content in reports"#;
 
 pub struct NSFWDetector {
     model: nsfw::Model,
@@ -41,40 +18,19 @@ pub struct NSFWDetector {
 }
 
 impl NSFWDetector {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
-        let mut config = Self::load_config()?;
-        let model_data = Self::ensure_model(&mut config)?;
+    /// Loads the model, downloading it first if it is missing or outdated.
+    /// `client` is only used for the download.
+    pub fn new(client: &Client) -> Result<Self, Box<dyn Error>> {
+        let mut config = config::load_config()?;
+        let model_data = Self::ensure_model(&mut config, client)?;
         let model = create_model(&model_data[..])?;
 
-        let detector = NSFWDetector { model, config };
-        detector.save_updated_config()?;
-
-        Ok(detector)
+        Ok(NSFWDetector { model, config })
     }
 
-    fn load_config() -> Result<Table, Box<dyn Error>> {
-        let config_path = Path::new(CONFIG_PATH);
-        if !config_path.exists() {
-            println!("Config file not found, creating default config...");
-            if let Some(parent) = config_path.parent() {
-                fs::create_dir_all(parent)?;
-            }
-            fs::write(config_path, DEFAULT_CONFIG)?;
-            println!("Default config file created at {}", CONFIG_PATH);
-        }
-
-        let config_str = fs::read_to_string(config_path)?;
-        let config: Table = toml::from_str(&config_str)?;
-        Ok(config)
-    }
-
-    fn get_latest_release_info() -> Result<(String, String), Box<dyn Error>> {
+    fn get_latest_release_info(client: &Client) -> Result<(String, String), Box<dyn Error>> {
         println!("Checking for latest model version on GitHub...");
 
-        let client = reqwest::blocking::Client::builder()
-            .user_agent("OpenDirectoryScanner/0.1.0")
-            .build()?;
-
         let response = client.get(GITHUB_API_RELEASES).send()?;
         if !response.status().is_success() {
             return Err(format!(
@@ -117,13 +73,7 @@ impl NSFWDetector {
         ))
     }
 
-    fn save_updated_config(&self) -> Result<(), Box<dyn Error>> {
-        let config_str = toml::to_string(&self.config)?;
-        fs::write(CONFIG_PATH, config_str)?;
-        Ok(())
-    }
-
-    fn ensure_model(config: &mut Table) -> Result<Vec<u8>, Box<dyn Error>> {
+    fn ensure_model(config: &mut Table, client: &Client) -> Result<Vec<u8>, Box<dyn Error>> {
         let model_config = match config.get_mut("model") {
             Some(Value::Table(table)) => table,
             _ => {
@@ -158,7 +108,7 @@ impl NSFWDetector {
 
         let path = Path::new(&model_path);
 
-        let (latest_version, download_url) = Self::get_latest_release_info()?;
+        let (latest_version, download_url) = Self::get_latest_release_info(client)?;
 
         let needs_download = if !path.exists() {
             println!(
@@ -183,7 +133,7 @@ impl NSFWDetector {
             }
 
             println!("Downloading model from {}", download_url);
-            let mut resp = reqwest::blocking::get(&download_url)?;
+            let mut resp = client.get(&download_url).send()?;
             if !resp.status().is_success() {
                 return Err(format!("Failed to download model: HTTP {}", resp.status()).into());
             }
@@ -198,6 +148,16 @@ impl NSFWDetector {
                 .as_secs()
                 .to_string();
 
+            // Only a new model is recorded, so the config file keeps its
+            // comments otherwise.
+            config::save_values(
+                "model",
+                &[
+                    ("version", &latest_version),
+                    ("last_updated", &timestamp),
+                    ("url", &download_url),
+                ],
+            )?;
             model_config.insert("version".to_string(), Value::String(latest_version.clone()));
             model_config.insert("last_updated".to_string(), Value::String(timestamp));
             model_config.insert("url".to_string(), Value::String(download_url));
@@ -213,13 +173,9 @@ impl NSFWDetector {
         }
     }
 
-    pub fn is_nsfw(&self, image_url: &str, client: &Client) -> Result<bool, Box<dyn Error>> {
-        let response = client.get(image_url).send()?;
-        if !response.status().is_success() {
-            return Err(format!("Failed to download image: {}", response.status()).into());
-        }
-
-        let image_data = response.bytes()?;
+    /// Classifies the downloaded image `image_data`; `image_url` is only
+    /// used for logging.
+    pub fn is_nsfw(&self, image_url: &str, image_data: &[u8]) -> Result<bool, Box<dyn Error>> {
         let img = ImageReader::new(Cursor::new(image_data))
             .with_guessed_format()?
             .decode()?;
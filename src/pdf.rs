@@ -0,0 +1,851 @@
+use crate::backend::Backend;
+use crate::document::{self, DocumentMetadata};
+use flate2::read::ZlibDecoder;
+use roxmltree::Document;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+
+/// Bytes fetched from the end of a PDF to find `startxref` and the trailer.
+const PDF_TAIL: u64 = 16_384;
+
+/// Bytes fetched at once when reading an object or a cross-reference table.
+const CHUNK: usize = 8_192;
+
+/// Streams and cross-reference tables larger than this are not downloaded.
+const MAX_STREAM: usize = 16 * 1_048_576;
+
+/// Incremental updates followed through `/Prev` before giving up.
+const MAX_XREF_SECTIONS: usize = 32;
+
+/// Objects nested deeper than this in arrays and dictionaries are rejected.
+const MAX_DEPTH: usize = 64;
+
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+const XMP_NAMESPACE: &str = "http://ns.adobe.com/xap/1.0/";
+const PDF_NAMESPACE: &str = "http://ns.adobe.com/pdf/1.3/";
+
+/// A PDF object, as far as reading metadata needs it. References keep only
+/// the object number.
+#[derive(Debug, Clone)]
+enum Object {
+    Null,
+    Number(f64),
+    String(Vec<u8>),
+    Name(String),
+    Array(Vec<Object>),
+    Dict(HashMap<String, Object>),
+    Ref(u32),
+    Other,
+}
+
+impl Object {
+    fn get(&self, key: &str) -> Option<&Object> {
+        match self {
+            Object::Dict(dict) => dict.get(key),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> Option<u64> {
+        match self {
+            Object::Number(n) if *n >= 0.0 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> Option<&str> {
+        match self {
+            Object::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Decodes a text string: UTF-16BE with a byte order mark, UTF-8 with
+    /// one, or else PDFDocEncoding, read as Latin-1.
+    fn text(&self) -> Option<String> {
+        let Object::String(bytes) = self else {
+            return None;
+        };
+        let text = if let Some(utf16) = bytes.strip_prefix(b"\xFE\xFF") {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        } else if let Some(utf8) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+            String::from_utf8_lossy(utf8).into_owned()
+        } else {
+            bytes.iter().map(|b| *b as char).collect()
+        };
+        let text = text.trim_matches(char::from(0)).trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b'\0' | b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    is_whitespace(byte) || b"()<>[]{}/%".contains(&byte)
+}
+
+/// Reads PDF objects from a buffer.
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Arrays and dictionaries currently open.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Parser {
+            data,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek() {
+            match byte {
+                b'%' => {
+                    while !matches!(self.peek(), None | Some(b'\r' | b'\n')) {
+                        self.pos += 1;
+                    }
+                }
+                byte if is_whitespace(byte) => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    /// Reads a run of regular characters: a keyword, number or name.
+    fn word(&mut self) -> &'a [u8] {
+        let start = self.pos;
+        while self.peek().is_some_and(|byte| !is_delimiter(byte)) {
+            self.pos += 1;
+        }
+        &self.data[start..self.pos]
+    }
+
+    fn keyword(&mut self) -> Option<&'a [u8]> {
+        self.skip_whitespace();
+        let word = self.word();
+        (!word.is_empty()).then_some(word)
+    }
+
+    fn integer(&mut self) -> Option<u64> {
+        std::str::from_utf8(self.keyword()?).ok()?.parse().ok()
+    }
+
+    fn literal_string(&mut self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut depth = 0;
+        while let Some(byte) = self.peek() {
+            self.pos += 1;
+            match byte {
+                b'(' => depth += 1,
+                b')' if depth == 0 => break,
+                b')' => depth -= 1,
+                b'\\' => {
+                    let Some(escaped) = self.peek() else {
+                        break;
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'b' => bytes.push(0x08),
+                        b'f' => bytes.push(0x0C),
+                        b'0'..=b'7' => {
+                            let mut value = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(digit @ b'0'..=b'7') => {
+                                        value = value * 8 + (digit - b'0') as u32;
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            bytes.push(value as u8);
+                        }
+                        // A backslash at the end of a line continues it.
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        other => bytes.push(other),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            bytes.push(byte);
+        }
+        bytes
+    }
+
+    fn hex_string(&mut self) -> Vec<u8> {
+        let mut digits = Vec::new();
+        while let Some(byte) = self.peek() {
+            self.pos += 1;
+            match byte {
+                b'>' => break,
+                byte if byte.is_ascii_hexdigit() => digits.push(byte),
+                _ => {}
+            }
+        }
+        if digits.len() % 2 == 1 {
+            digits.push(b'0');
+        }
+        digits
+            .chunks(2)
+            .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+            .collect()
+    }
+
+    /// Reads the next object. Returns `None` at a keyword such as
+    /// `stream` or `endobj`, at the end of the buffer, or when nested too
+    /// deeply.
+    fn object(&mut self) -> Option<Object> {
+        if self.depth == MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let object = self.next_object();
+        self.depth -= 1;
+        object
+    }
+
+    fn next_object(&mut self) -> Option<Object> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'/' => {
+                self.pos += 1;
+                Some(Object::Name(
+                    String::from_utf8_lossy(self.word()).into_owned(),
+                ))
+            }
+            b'<' if self.data.get(self.pos + 1) == Some(&b'<') => {
+                self.pos += 2;
+                let mut dict = HashMap::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.data[self.pos..].starts_with(b">>") {
+                        self.pos += 2;
+                        return Some(Object::Dict(dict));
+                    }
+                    let Object::Name(key) = self.object()? else {
+                        return None;
+                    };
+                    let value = self.object()?;
+                    dict.insert(key, value);
+                }
+            }
+            b'<' => {
+                self.pos += 1;
+                Some(Object::String(self.hex_string()))
+            }
+            b'(' => {
+                self.pos += 1;
+                Some(Object::String(self.literal_string()))
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut array = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek()? == b']' {
+                        self.pos += 1;
+                        return Some(Object::Array(array));
+                    }
+                    array.push(self.object()?);
+                }
+            }
+            b'0'..=b'9' | b'+' | b'-' | b'.' => {
+                let number: f64 = std::str::from_utf8(self.word()).ok()?.parse().ok()?;
+                // `12 0 R` is a reference to object 12.
+                let after_number = self.pos;
+                if let Some(_generation) = self.integer()
+                    && self.keyword() == Some(b"R")
+                {
+                    return Some(Object::Ref(number as u32));
+                }
+                self.pos = after_number;
+                Some(Object::Number(number))
+            }
+            b')' | b'>' | b']' | b'{' | b'}' => None,
+            _ => match self.word() {
+                b"null" => Some(Object::Null),
+                b"true" | b"false" => Some(Object::Other),
+                word => {
+                    self.pos -= word.len();
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// An object with the decoded data of its stream, if it is one.
+type StoredObject = (Object, Option<Vec<u8>>);
+
+/// Where an object is stored: at an offset in the file, or inside an
+/// object stream.
+enum XrefEntry {
+    Offset(u64),
+    Compressed(u32, usize),
+}
+
+/// Undoes the PNG predictors used by cross-reference streams, for rows of
+/// `columns` one-byte samples each prefixed by a filter type.
+fn unpredict(data: &[u8], columns: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut previous = vec![0u8; columns];
+    for row in data.chunks(columns + 1) {
+        let (filter, row) = row.split_first().unwrap_or((&0, &[]));
+        let mut current = vec![0u8; columns];
+        for (i, byte) in row.iter().enumerate() {
+            let left = if i > 0 { current[i - 1] } else { 0 };
+            let up = previous[i];
+            let up_left = if i > 0 { previous[i - 1] } else { 0 };
+            current[i] = match filter {
+                1 => byte.wrapping_add(left),
+                2 => byte.wrapping_add(up),
+                3 => byte.wrapping_add(((left as u16 + up as u16) / 2) as u8),
+                4 => {
+                    let estimate = left as i16 + up as i16 - up_left as i16;
+                    let (a, b, c) = (
+                        (estimate - left as i16).abs(),
+                        (estimate - up as i16).abs(),
+                        (estimate - up_left as i16).abs(),
+                    );
+                    let predictor = if a <= b && a <= c {
+                        left
+                    } else if b <= c {
+                        up
+                    } else {
+                        up_left
+                    };
+                    byte.wrapping_add(predictor)
+                }
+                _ => *byte,
+            };
+        }
+        output.extend_from_slice(&current[..row.len()]);
+        previous = current;
+    }
+    output
+}
+
+/// Applies a stream's filters. Only `FlateDecode`, by far the most common
+/// one for metadata and cross-reference streams, is supported.
+fn decode(dict: &Object, raw: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let filters = match dict.get("Filter") {
+        None => vec![],
+        Some(Object::Array(filters)) => filters.iter().filter_map(Object::name).collect(),
+        Some(filter) => filter.name().into_iter().collect(),
+    };
+    let mut data = raw;
+    for filter in filters {
+        if filter != "FlateDecode" {
+            return Err(format!("unsupported stream filter {}", filter).into());
+        }
+        let mut decoded = Vec::new();
+        ZlibDecoder::new(data.as_slice())
+            .take(MAX_STREAM as u64)
+            .read_to_end(&mut decoded)?;
+        data = decoded;
+    }
+    let params = dict.get("DecodeParms");
+    if params
+        .and_then(|p| p.get("Predictor"))
+        .and_then(Object::number)
+        .is_some_and(|predictor| predictor >= 10)
+    {
+        let columns = params
+            .and_then(|p| p.get("Columns"))
+            .and_then(Object::number)
+            .unwrap_or(1) as usize;
+        if columns == 0 || columns > MAX_STREAM {
+            return Err(format!("invalid predictor columns {}", columns).into());
+        }
+        data = unpredict(&data, columns);
+    }
+    Ok(data)
+}
+
+/// Parses the indirect object (`12 0 obj ...`) at the start of `data`,
+/// returning it and the position after it.
+fn parse_indirect(data: &[u8], offset: u64) -> Result<(Object, usize), Box<dyn Error>> {
+    let mut parser = Parser::new(data);
+    let (_number, _generation) = (parser.integer(), parser.integer());
+    if parser.keyword() != Some(b"obj") {
+        return Err(format!("no object at offset {}", offset).into());
+    }
+    let object = parser.object().ok_or("invalid object")?;
+    Ok((object, parser.pos))
+}
+
+/// A remote PDF, read through its cross-reference table.
+struct Pdf<'a> {
+    url: &'a str,
+    backend: &'a dyn Backend,
+    tail_start: u64,
+    tail: Vec<u8>,
+    xref: HashMap<u32, XrefEntry>,
+    /// Decoded object streams, with where each of their objects starts.
+    object_streams: HashMap<u32, (Vec<usize>, Vec<u8>)>,
+}
+
+impl Pdf<'_> {
+    /// Reads up to `length` bytes at `offset`, from the tail if it has them.
+    fn read(&self, offset: u64, length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        if let Some(start) = offset.checked_sub(self.tail_start) {
+            let start = start as usize;
+            let end = start.saturating_add(length).min(self.tail.len());
+            return Ok(self.tail.get(start..end).unwrap_or_default().to_vec());
+        }
+        self.backend.fetch_range(self.url, offset, length)
+    }
+
+    /// Reads the indirect object at `offset`, with its decoded stream data
+    /// if it is a stream.
+    fn object_at(&mut self, offset: u64) -> Result<StoredObject, Box<dyn Error>> {
+        let data = self.read(offset, CHUNK)?;
+        let (object, end) = parse_indirect(&data, offset)?;
+        let mut parser = Parser::new(&data);
+        parser.pos = end;
+        if parser.keyword() != Some(b"stream") {
+            return Ok((object, None));
+        }
+
+        let mut start = parser.pos;
+        if data.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if data.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+        // A length stored separately is a plain number object at an offset;
+        // it is read directly so that crafted files cannot recurse.
+        let length = match object.get("Length") {
+            Some(Object::Ref(n)) => match self.xref.get(n) {
+                Some(XrefEntry::Offset(length_offset)) => {
+                    let length_offset = *length_offset;
+                    parse_indirect(&self.read(length_offset, CHUNK)?, length_offset)?
+                        .0
+                        .number()
+                }
+                _ => None,
+            },
+            Some(length) => length.number(),
+            None => None,
+        }
+        .ok_or("stream without a length")? as usize;
+        if length > MAX_STREAM {
+            return Err(format!("stream of {} bytes is too large", length).into());
+        }
+        let raw = match data.get(start..start + length) {
+            Some(raw) => raw.to_vec(),
+            None => {
+                let stream_offset = offset
+                    .checked_add(start as u64)
+                    .ok_or("invalid stream offset")?;
+                self.read(stream_offset, length)?
+            }
+        };
+        let stream = decode(&object, raw)?;
+        Ok((object, Some(stream)))
+    }
+
+    /// Reads object `number`, wherever it is stored.
+    fn object(&mut self, number: u32) -> Result<StoredObject, Box<dyn Error>> {
+        match self.xref.get(&number) {
+            Some(XrefEntry::Offset(offset)) => self.object_at(*offset),
+            Some(XrefEntry::Compressed(stream, index)) => {
+                let (stream, index) = (*stream, *index);
+                if !self.object_streams.contains_key(&stream) {
+                    let loaded = self.load_object_stream(stream)?;
+                    self.object_streams.insert(stream, loaded);
+                }
+                let (starts, objects) = &self.object_streams[&stream];
+                let start = *starts.get(index).ok_or_else(|| {
+                    format!("object {} is missing from its object stream", number)
+                })?;
+                let object = Parser::new(objects.get(start..).unwrap_or_default())
+                    .object()
+                    .ok_or("invalid object in object stream")?;
+                Ok((object, None))
+            }
+            None => Err(format!("object {} is not in the cross-reference table", number).into()),
+        }
+    }
+
+    /// Reads an object stream, returning where each object starts and the
+    /// decoded stream.
+    fn load_object_stream(&mut self, number: u32) -> Result<(Vec<usize>, Vec<u8>), Box<dyn Error>> {
+        // Object streams cannot themselves be compressed.
+        let Some(XrefEntry::Offset(offset)) = self.xref.get(&number) else {
+            return Err(format!("object stream {} is not stored at an offset", number).into());
+        };
+        let (dict, data) = self.object_at(*offset)?;
+        let data = data.ok_or("object stream without data")?;
+        // A header of object number and offset pairs precedes the objects.
+        let first = dict.get("First").and_then(Object::number).unwrap_or(0) as usize;
+        let mut header = Parser::new(data.get(..first).unwrap_or_default());
+        let pairs: Vec<u64> = std::iter::from_fn(|| header.integer()).collect();
+        let starts = pairs
+            .chunks(2)
+            .filter_map(|pair| first.checked_add(usize::try_from(*pair.get(1)?).ok()?))
+            .collect();
+        Ok((starts, data))
+    }
+
+    fn resolve(&mut self, object: Option<&Object>) -> Result<Option<Object>, Box<dyn Error>> {
+        match object {
+            Some(Object::Ref(number)) => Ok(Some(self.object(*number)?.0)),
+            Some(object) => Ok(Some(object.clone())),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads a classic `xref` table at `offset`, returning its trailer.
+    fn read_xref_table(&mut self, offset: u64) -> Result<Object, Box<dyn Error>> {
+        let mut requested = CHUNK;
+        let mut data = self.read(offset, requested)?;
+        // Subsections of entries follow the `xref` keyword, then the trailer.
+        let mut pos = 4;
+        loop {
+            let mut parser = Parser::new(&data);
+            parser.pos = pos;
+            let (start, count) = match parser.keyword() {
+                Some(b"trailer") => {
+                    return parser.object().ok_or_else(|| "invalid trailer".into());
+                }
+                Some(word) => {
+                    let start: u32 = std::str::from_utf8(word)?.parse()?;
+                    let count = parser.integer().ok_or("invalid xref subsection")?;
+                    (start, count as usize)
+                }
+                None => return Err("cross-reference table without a trailer".into()),
+            };
+            let entries = parser.pos;
+
+            // Entries are 20 bytes each. A read shorter than requested
+            // reached the end of the file; otherwise fetch the rest.
+            let needed = count
+                .checked_mul(20)
+                .and_then(|size| size.checked_add(entries + CHUNK))
+                .ok_or("invalid xref subsection")?;
+            if data.len() == requested && data.len() < needed {
+                if needed > MAX_STREAM {
+                    return Err("cross-reference table is too large".into());
+                }
+                requested = needed;
+                data = self.read(offset, requested)?;
+            }
+            let mut parser = Parser::new(&data);
+            parser.pos = entries;
+            for i in 0..count as u32 {
+                let entry_offset = parser.integer().ok_or("truncated xref table")?;
+                parser.integer();
+                if parser.keyword() == Some(b"n") {
+                    let number = start.checked_add(i).ok_or("invalid xref subsection")?;
+                    self.xref
+                        .entry(number)
+                        .or_insert(XrefEntry::Offset(entry_offset));
+                }
+            }
+            pos = parser.pos;
+        }
+    }
+
+    /// Reads a cross-reference stream at `offset`, returning its dictionary.
+    fn read_xref_stream(&mut self, offset: u64) -> Result<Object, Box<dyn Error>> {
+        let (dict, data) = self.object_at(offset)?;
+        if dict.get("Type").and_then(Object::name) != Some("XRef") {
+            return Err(format!("no cross-reference data at offset {}", offset).into());
+        }
+        let data = data.ok_or("cross-reference stream without data")?;
+        let widths: Vec<usize> = match dict.get("W") {
+            Some(Object::Array(widths)) => widths
+                .iter()
+                .map(|w| w.number().unwrap_or(0) as usize)
+                .collect(),
+            _ => return Err("cross-reference stream without /W".into()),
+        };
+        if widths.len() != 3 || widths.iter().any(|width| *width > 8) {
+            return Err("invalid /W in cross-reference stream".into());
+        }
+        let index: Vec<u64> = match dict.get("Index") {
+            Some(Object::Array(index)) => index.iter().filter_map(Object::number).collect(),
+            _ => vec![0, dict.get("Size").and_then(Object::number).unwrap_or(0)],
+        };
+
+        let row_length: usize = widths.iter().sum();
+        let mut rows = data.chunks_exact(row_length.max(1));
+        for section in index.chunks(2) {
+            let (start, count) = (section[0] as u32, section.get(1).copied().unwrap_or(0));
+            for i in 0..count as u32 {
+                let Some(row) = rows.next() else {
+                    break;
+                };
+                let mut fields = [0u64; 3];
+                let mut pos = 0;
+                for (field, width) in fields.iter_mut().zip(&widths) {
+                    *field = row[pos..pos + width]
+                        .iter()
+                        .fold(0, |value, byte| (value << 8) | *byte as u64);
+                    pos += width;
+                }
+                // The type defaults to 1 when its field is left out.
+                let kind = if widths[0] == 0 { 1 } else { fields[0] };
+                let entry = match kind {
+                    1 => XrefEntry::Offset(fields[1]),
+                    2 => XrefEntry::Compressed(fields[1] as u32, fields[2] as usize),
+                    _ => continue,
+                };
+                let number = start
+                    .checked_add(i)
+                    .ok_or("invalid cross-reference stream index")?;
+                self.xref.entry(number).or_insert(entry);
+            }
+        }
+        Ok(dict)
+    }
+
+    /// Loads every cross-reference section, newest first, and returns the
+    /// merged trailer.
+    fn load_xref(&mut self) -> Result<HashMap<String, Object>, Box<dyn Error>> {
+        let startxref = self
+            .tail
+            .windows(9)
+            .rposition(|w| w == b"startxref")
+            .ok_or("no startxref")?;
+        let mut parser = Parser::new(&self.tail[startxref + 9..]);
+        let mut next = Some(parser.integer().ok_or("invalid startxref")?);
+
+        let mut trailer = HashMap::new();
+        for _ in 0..MAX_XREF_SECTIONS {
+            let Some(offset) = next else {
+                break;
+            };
+            let head = self.read(offset, 4)?;
+            let section = match head.as_slice() {
+                b"xref" => self.read_xref_table(offset)?,
+                _ => self.read_xref_stream(offset)?,
+            };
+            let Object::Dict(section) = section else {
+                break;
+            };
+            next = section.get("Prev").and_then(Object::number);
+            for (key, value) in section {
+                trailer.entry(key).or_insert(value);
+            }
+        }
+        Ok(trailer)
+    }
+}
+
+/// Formats a PDF date (`D:YYYYMMDDHHmmSS` and a time zone) as
+/// `YYYY-MM-DD HH:MM:SS`, leaving out the time zone.
+fn format_pdf_date(value: String) -> String {
+    let digits: String = value
+        .trim_start_matches("D:")
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    if digits.len() < 4 {
+        return value;
+    }
+    let part =
+        |start: usize, default: &'static str| digits.get(start..start + 2).unwrap_or(default);
+    format!(
+        "{}-{}-{} {}:{}:{}",
+        &digits[..4],
+        part(4, "01"),
+        part(6, "01"),
+        part(8, "00"),
+        part(10, "00"),
+        part(12, "00")
+    )
+}
+
+/// Fills the gaps left by the info dictionary from the XMP packet.
+fn read_xmp(xml: &str, metadata: &mut DocumentMetadata) {
+    let Ok(xmp) = Document::parse(xml) else {
+        return;
+    };
+    let value = |namespace: &str, name: &str| {
+        xmp.descendants().find_map(|node| {
+            if let Some(value) = node.attribute((namespace, name)) {
+                return Some(value.trim().to_string());
+            }
+            if node.tag_name().namespace() != Some(namespace) || node.tag_name().name() != name {
+                return None;
+            }
+            // Titles and authors are wrapped in rdf:Alt or rdf:Seq lists.
+            node.descendants()
+                .filter_map(|n| n.text())
+                .map(str::trim)
+                .find(|text| !text.is_empty())
+                .map(str::to_string)
+        })
+    };
+    let fields = [
+        (&mut metadata.title, value(DC_NAMESPACE, "title")),
+        (&mut metadata.author, value(DC_NAMESPACE, "creator")),
+        (&mut metadata.creator, value(XMP_NAMESPACE, "CreatorTool")),
+        (&mut metadata.producer, value(PDF_NAMESPACE, "Producer")),
+        (
+            &mut metadata.created,
+            value(XMP_NAMESPACE, "CreateDate").map(document::format_iso_time),
+        ),
+        (
+            &mut metadata.modified,
+            value(XMP_NAMESPACE, "ModifyDate").map(document::format_iso_time),
+        ),
+    ];
+    for (field, value) in fields {
+        if field.is_none() {
+            *field = value.filter(|v| !v.is_empty());
+        }
+    }
+}
+
+/// Reads the info dictionary, XMP metadata and page count of the PDF at
+/// `url`. Only the trailer, the cross-reference data and the few objects
+/// involved are downloaded.
+pub fn read(url: &str, backend: &dyn Backend) -> Result<DocumentMetadata, Box<dyn Error>> {
+    let (tail_start, tail) = backend.fetch_tail(url, PDF_TAIL)?;
+    let mut pdf = Pdf {
+        url,
+        backend,
+        tail_start,
+        tail,
+        xref: HashMap::new(),
+        object_streams: HashMap::new(),
+    };
+    let trailer = pdf.load_xref()?;
+    let mut metadata = DocumentMetadata::default();
+
+    let root = pdf
+        .resolve(trailer.get("Root"))?
+        .ok_or("no document catalog")?;
+    let pages = pdf.resolve(root.get("Pages"))?;
+    metadata.pages = pages
+        .as_ref()
+        .and_then(|p| p.get("Count"))
+        .and_then(Object::number)
+        .map(|count| count as u32);
+
+    // The strings of encrypted documents are encrypted too.
+    if trailer.contains_key("Encrypt") {
+        return Ok(metadata);
+    }
+    if let Some(info) = pdf.resolve(trailer.get("Info"))? {
+        let text = |key| info.get(key).and_then(Object::text);
+        metadata.title = text("Title");
+        metadata.author = text("Author");
+        metadata.creator = text("Creator");
+        metadata.producer = text("Producer");
+        metadata.created = text("CreationDate").map(format_pdf_date);
+        metadata.modified = text("ModDate").map(format_pdf_date);
+    }
+    if let Some(Object::Ref(number)) = root.get("Metadata")
+        && let Ok((_, Some(xmp))) = pdf.object(*number)
+    {
+        read_xmp(&String::from_utf8_lossy(&xmp), &mut metadata);
+    }
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryBackend;
+
+    /// Builds a PDF from the bodies of objects 1, 2, ... with a classic
+    /// cross-reference table starting with `subsection`, if given.
+    fn pdf(objects: &[&str], subsection: Option<&str>) -> Vec<u8> {
+        let mut data = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(data.len());
+            data.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).bytes());
+        }
+        let xref = data.len();
+        let subsection = subsection
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("1 {}", objects.len()));
+        data.extend(format!("xref\n{}\n", subsection).bytes());
+        for offset in offsets {
+            data.extend(format!("{:010} 00000 n \n", offset).bytes());
+        }
+        let trailer = "<< /Root 1 0 R /Info 3 0 R >>";
+        data.extend(format!("trailer\n{}\nstartxref\n{}\n%%EOF\n", trailer, xref).bytes());
+        data
+    }
+
+    fn read_bytes(data: Vec<u8>) -> Result<DocumentMetadata, Box<dyn Error>> {
+        read("memory:///file.pdf", &MemoryBackend(data))
+    }
+
+    #[test]
+    fn reads_info_and_page_count() {
+        let data = pdf(
+            &[
+                "<< /Type /Catalog /Pages 2 0 R >>",
+                "<< /Type /Pages /Count 3 >>",
+                "<< /Title (Annual \\(draft\\)) /Author <416E6E> /CreationDate (D:20240102030405Z) >>",
+            ],
+            None,
+        );
+        let metadata = read_bytes(data).unwrap();
+        assert_eq!(metadata.pages, Some(3));
+        assert_eq!(metadata.title.as_deref(), Some("Annual (draft)"));
+        assert_eq!(metadata.author.as_deref(), Some("Ann"));
+        assert_eq!(metadata.created.as_deref(), Some("2024-01-02 03:04:05"));
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth| format!("{}1{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(
+            Parser::new(nested(MAX_DEPTH - 1).as_bytes())
+                .object()
+                .is_some()
+        );
+        assert!(Parser::new(nested(MAX_DEPTH).as_bytes()).object().is_none());
+        assert!(
+            Parser::new("<<".repeat(100_000).as_bytes())
+                .object()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn rejects_overflowing_xref_subsection() {
+        let objects = ["<< /Pages 2 0 R >>", "<< /Count 1 >>", "<< >>"];
+        assert!(read_bytes(pdf(&objects, Some("4294967295 3"))).is_err());
+        assert!(read_bytes(pdf(&objects, Some("1 18446744073709551615"))).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        assert!(read_bytes(b"%PDF-1.4\nstartxref\n999999\n%%EOF".to_vec()).is_err());
+        assert!(read_bytes(b"%PDF-1.4\n1 0 obj\n<< /Length 5 >>\nstream\nab".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use crate::http::HttpClient;
+use reqwest::StatusCode;
+use reqwest::blocking::Response;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use std::error::Error;
+
+/// What a probe request learned about a file.
+#[derive(Debug, Default)]
+pub struct Probe {
+    pub size: Option<u64>,
+    /// MIME type without parameters, e.g. `image/jpeg`.
+    pub content_type: Option<String>,
+}
+
+fn content_type(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .filter(|v| !v.is_empty())
+}
+
+fn content_length(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Extracts the complete length from a `Content-Range: bytes 0-0/12345`
+/// header. Returns `None` when the length is unknown (`*`).
+fn content_range_total(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit('/').next())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// Determines the size and type of a remote file without downloading it.
+/// A HEAD request is tried first; servers that reject HEAD or omit
+/// `Content-Length` are asked for a single byte with a Range request.
+pub fn probe(url: &str, client: &HttpClient) -> Result<Probe, Box<dyn Error>> {
+    if let Ok(response) = client.head(url)
+        && response.status().is_success()
+        && let Some(size) = content_length(&response)
+    {
+        return Ok(Probe {
+            size: Some(size),
+            content_type: content_type(&response),
+        });
+    }
+
+    let response = client.execute(|c| c.get(url).header(RANGE, "bytes=0-0"))?;
+    let size = match response.status() {
+        StatusCode::PARTIAL_CONTENT => content_range_total(&response),
+        status if status.is_success() => content_length(&response),
+        status => return Err(format!("Failed to probe {}: {}", url, status).into()),
+    };
+    Ok(Probe {
+        size,
+        content_type: content_type(&response),
+    })
+}
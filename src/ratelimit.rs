@@ -0,0 +1,86 @@
+use crate::urls;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+struct HostState {
+    tokens: f64,
+    refilled: Instant,
+    last_request: Option<Instant>,
+}
+
+/// Throttles requests per host with a token bucket and an optional fixed
+/// delay between consecutive requests. The bucket holds one second's worth
+/// of tokens, so short bursts up to the rate are allowed.
+pub struct RateLimiter {
+    requests_per_second: Option<f64>,
+    delay: Duration,
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: Option<f64>, delay: Duration) -> Self {
+        RateLimiter {
+            requests_per_second: requests_per_second.filter(|rate| *rate > 0.0),
+            delay,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.requests_per_second.is_some() || !self.delay.is_zero()
+    }
+
+    /// Blocks until a request to the host of `url` is allowed.
+    pub fn wait(&self, url: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        let Some(host) = Url::parse(url).ok().as_ref().and_then(urls::host_key) else {
+            return;
+        };
+
+        loop {
+            let wait = {
+                let mut hosts = self.hosts.lock().unwrap();
+                let now = Instant::now();
+                let capacity = self.requests_per_second.map_or(1.0, |rate| rate.max(1.0));
+                let state = hosts.entry(host.clone()).or_insert(HostState {
+                    tokens: capacity,
+                    refilled: now,
+                    last_request: None,
+                });
+
+                if let Some(rate) = self.requests_per_second {
+                    let elapsed = now.duration_since(state.refilled).as_secs_f64();
+                    state.tokens = (state.tokens + elapsed * rate).min(capacity);
+                    state.refilled = now;
+                }
+
+                let token_wait = match self.requests_per_second {
+                    Some(rate) if state.tokens < 1.0 => {
+                        Duration::from_secs_f64((1.0 - state.tokens) / rate)
+                    }
+                    _ => Duration::ZERO,
+                };
+                let delay_wait = state
+                    .last_request
+                    .map(|last| (last + self.delay).saturating_duration_since(now))
+                    .unwrap_or_default();
+
+                let wait = token_wait.max(delay_wait);
+                if wait.is_zero() {
+                    if self.requests_per_second.is_some() {
+                        state.tokens -= 1.0;
+                    }
+                    state.last_request = Some(now);
+                    return;
+                }
+                wait
+            };
+            thread::sleep(wait);
+        }
+    }
+}
@@ -1,40 +1,185 @@
+use crate::archive::ArchiveEntry;
+use crate::document::DocumentMetadata;
+use crate::imagemeta::{ImageMetadata, RESOLUTION_CLASSES};
+use crate::media::MediaMetadata;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 use std::io::Write;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FileType {
     Directory,
-    Image(String),
-    Video(String),
-    Audio(String),
-    Document(String),
-    Other(String),
+    /// A file in one of the `[file_types]` categories, e.g. "Image", with
+    /// its format, e.g. "JPEG".
+    File {
+        category: String,
+        format: String,
+    },
 }
 
-#[derive(Debug, Clone)]
+impl FileType {
+    pub fn file(category: &str, format: &str) -> Self {
+        FileType::File {
+            category: category.to_string(),
+            format: format.to_string(),
+        }
+    }
+
+    pub fn category(&self) -> Option<&str> {
+        match self {
+            FileType::File { category, .. } => Some(category),
+            FileType::Directory => None,
+        }
+    }
+
+    pub fn format(&self) -> Option<&str> {
+        match self {
+            FileType::File { format, .. } => Some(format),
+            FileType::Directory => None,
+        }
+    }
+
+    pub fn is(&self, category: &str) -> bool {
+        self.category() == Some(category)
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            FileType::File { category, format } => format!("{} ({})", category, format),
+            FileType::Directory => "Directory".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SizeSource {
+    Listing,
+    Header,
+    Unknown,
+}
+
+impl SizeSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SizeSource::Listing => "listing",
+            SizeSource::Header => "header",
+            SizeSource::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileInfo {
     pub name: String,
     pub url: String,
     pub size: u64,
+    pub size_source: SizeSource,
+    pub modified: Option<String>,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    /// The type claimed by the file name.
     pub file_type: FileType,
+    /// The type found in the file's first bytes, when sniffing is enabled
+    /// and the format was recognized.
+    pub detected_type: Option<FileType>,
+    /// Set when the detected type contradicts the file name.
+    #[serde(default)]
+    pub type_mismatch: bool,
+    /// Contents of a ZIP archive, when archive listing is enabled.
+    #[serde(default)]
+    pub archive_entries: Option<Vec<ArchiveEntry>>,
+    /// Dimensions and EXIF data of an image.
+    #[serde(default)]
+    pub image: Option<ImageMetadata>,
+    /// Duration, codecs and tags of an audio or video file.
+    #[serde(default)]
+    pub media: Option<MediaMetadata>,
+    /// Author, title and similar metadata of a PDF or Office document.
+    #[serde(default)]
+    pub document: Option<DocumentMetadata>,
+    /// Set for images too large to be downloaded for the NSFW check.
+    #[serde(default)]
+    pub nsfw_unchecked: bool,
+}
+
+impl FileInfo {
+    /// The type the file is counted as: the claimed type, unless the
+    /// content contradicts it or the name has no extension.
+    pub fn effective_type(&self) -> &FileType {
+        match &self.detected_type {
+            Some(detected) if self.type_mismatch || self.file_type == FileType::Directory => {
+                detected
+            }
+            _ => &self.file_type,
+        }
+    }
+
+    /// Width and height of an image or video.
+    fn dimensions(&self) -> Option<(u32, u32)> {
+        let image = self
+            .image
+            .as_ref()
+            .and_then(|i| Some((i.width?, i.height?)));
+        image.or_else(|| {
+            self.media
+                .as_ref()
+                .and_then(|m| Some((m.width?, m.height?)))
+        })
+    }
+}
+
+/// Formats a duration in seconds as `H:MM:SS`.
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct DirectoryReport {
     pub total_files: usize,
     pub total_directories: usize,
     pub total_size: u64,
+    pub unknown_size_count: usize,
     pub files_by_type: HashMap<String, usize>,
-    pub image_count: usize,
-    pub video_count: usize,
-    pub audio_count: usize,
-    pub document_count: usize,
-    pub other_count: usize,
+    pub files_by_category: HashMap<String, usize>,
     pub nsfw_count: usize,
     pub nsfw_files: Vec<String>,
+    /// Images too large to be downloaded for the NSFW check.
+    pub nsfw_unchecked: Vec<String>,
     pub largest_file: Option<FileInfo>,
     pub largest_directory: Option<(String, u64)>,
+    pub duplicates_suppressed: usize,
+    pub loops_suppressed: usize,
+    pub external_references: Vec<String>,
+    pub filtered_count: usize,
+    /// Set when the scan was interrupted before every directory was listed.
+    pub incomplete: bool,
+    pub unvisited_directories: Vec<String>,
+    /// Directories that could not be listed, with the final error.
+    pub failed_directories: Vec<(String, String)>,
+    /// Paths skipped because robots.txt disallows them.
+    pub robots_excluded: Vec<String>,
+    /// Files whose content contradicts their name, with both types.
+    pub type_mismatches: Vec<(String, String)>,
+    /// Listed ZIP archives with their contents.
+    pub archives: Vec<(String, Vec<ArchiveEntry>)>,
+    /// Image counts per resolution class.
+    pub images_by_resolution: HashMap<String, usize>,
+    /// Images with GPS coordinates in their EXIF data, with the position.
+    pub geotagged_images: Vec<(String, String)>,
+    /// Summed duration of audio and video files, in seconds.
+    pub total_media_duration: f64,
+    /// Audio and video file counts per codec.
+    pub media_by_codec: HashMap<String, usize>,
+    /// Document counts per author, from document metadata.
+    pub documents_by_author: HashMap<String, usize>,
 }
 
 impl DirectoryReport {
@@ -43,21 +188,81 @@ impl DirectoryReport {
             total_files: 0,
             total_directories: 0,
             total_size: 0,
+            unknown_size_count: 0,
             files_by_type: HashMap::new(),
-            image_count: 0,
-            video_count: 0,
-            audio_count: 0,
-            document_count: 0,
-            other_count: 0,
+            files_by_category: HashMap::new(),
             nsfw_count: 0,
             nsfw_files: Vec::new(),
+            nsfw_unchecked: Vec::new(),
             largest_file: None,
             largest_directory: None,
+            duplicates_suppressed: 0,
+            loops_suppressed: 0,
+            external_references: Vec::new(),
+            filtered_count: 0,
+            incomplete: false,
+            unvisited_directories: Vec::new(),
+            failed_directories: Vec::new(),
+            robots_excluded: Vec::new(),
+            type_mismatches: Vec::new(),
+            archives: Vec::new(),
+            images_by_resolution: HashMap::new(),
+            geotagged_images: Vec::new(),
+            total_media_duration: 0.0,
+            media_by_codec: HashMap::new(),
+            documents_by_author: HashMap::new(),
         }
     }
 
+    /// File counts per category, largest first.
+    fn categories(&self) -> Vec<(&String, &usize)> {
+        let mut categories: Vec<_> = self.files_by_category.iter().collect();
+        categories.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        categories
+    }
+
+    /// File counts per codec, most common first.
+    fn codecs(&self) -> Vec<(&String, &usize)> {
+        let mut codecs: Vec<_> = self.media_by_codec.iter().collect();
+        codecs.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        codecs
+    }
+
+    /// Document counts per author, most prolific first.
+    fn authors(&self) -> Vec<(&String, &usize)> {
+        let mut authors: Vec<_> = self.documents_by_author.iter().collect();
+        authors.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        authors
+    }
+
+    /// Image counts per resolution class, smallest first.
+    fn resolutions(&self) -> Vec<(&str, usize)> {
+        RESOLUTION_CLASSES
+            .iter()
+            .filter_map(|(_, class)| Some((*class, *self.images_by_resolution.get(*class)?)))
+            .collect()
+    }
+
+    /// Number of files inside listed archives and their uncompressed size.
+    fn archive_totals(&self) -> (usize, u64) {
+        self.archives
+            .iter()
+            .flat_map(|(_, entries)| entries)
+            .fold((0, 0), |(count, size), entry| {
+                (count + 1, size + entry.size)
+            })
+    }
+
     pub fn print(&self) {
-        println!("=== Directory Scan Report ===");
+        if self.incomplete {
+            println!("=== Directory Scan Report (INCOMPLETE) ===");
+            println!(
+                "The scan was interrupted; {} directories were not visited.",
+                self.unvisited_directories.len()
+            );
+        } else {
+            println!("=== Directory Scan Report ===");
+        }
         println!("Total files: {}", self.total_files);
         println!("Total directories: {}", self.total_directories);
         println!(
@@ -65,14 +270,53 @@ impl DirectoryReport {
             self.total_size,
             self.total_size / 1_048_576
         );
+        if self.unknown_size_count > 0 {
+            println!("Files with unknown size: {}", self.unknown_size_count);
+        }
+        println!("Duplicate links suppressed: {}", self.duplicates_suppressed);
+        println!("Directory loops suppressed: {}", self.loops_suppressed);
+        if self.filtered_count > 0 {
+            println!("Entries excluded by filters: {}", self.filtered_count);
+        }
+        if !self.robots_excluded.is_empty() {
+            println!(
+                "Entries excluded by robots.txt: {}",
+                self.robots_excluded.len()
+            );
+        }
+        if !self.type_mismatches.is_empty() {
+            println!("Type mismatches: {}", self.type_mismatches.len());
+        }
+        if !self.geotagged_images.is_empty() {
+            println!("Geotagged images: {}", self.geotagged_images.len());
+        }
+        if !self.archives.is_empty() {
+            let (count, size) = self.archive_totals();
+            println!(
+                "Archives listed: {} ({} files, {} bytes uncompressed)",
+                self.archives.len(),
+                count,
+                size
+            );
+        }
+        if self.total_media_duration > 0.0 {
+            println!(
+                "Total media duration: {}",
+                format_duration(self.total_media_duration)
+            );
+        }
 
         println!("\n=== File Type Breakdown ===");
-        println!("Images: {} files", self.image_count);
-        println!("Videos: {} files", self.video_count);
-        println!("Audio: {} files", self.audio_count);
-        println!("Documents: {} files", self.document_count);
-        println!("Other: {} files", self.other_count);
+        for (category, count) in self.categories() {
+            println!("{}: {} files", category, count);
+        }
         println!("NSFW content: {} files", self.nsfw_count);
+        if !self.nsfw_unchecked.is_empty() {
+            println!(
+                "Not checked for NSFW content: {} files",
+                self.nsfw_unchecked.len()
+            );
+        }
 
         println!("\n=== Format Distribution ===");
         let mut formats: Vec<_> = self.files_by_type.iter().collect();
@@ -81,18 +325,68 @@ impl DirectoryReport {
             println!("{}: {} files", format, count);
         }
 
+        if !self.images_by_resolution.is_empty() {
+            println!("\n=== Image Resolutions ===");
+            for (class, count) in self.resolutions() {
+                println!("{}: {} files", class, count);
+            }
+        }
+
+        if !self.media_by_codec.is_empty() {
+            println!("\n=== Media Codecs ===");
+            for (codec, count) in self.codecs() {
+                println!("{}: {} files", codec, count);
+            }
+        }
+
+        if !self.documents_by_author.is_empty() {
+            println!("\n=== Document Authors ===");
+            for (author, count) in self.authors() {
+                println!("{}: {} files", author, count);
+            }
+        }
+
         if let Some(file) = &self.largest_file {
             println!("\n=== Largest File ===");
             println!("Name: {}", file.name);
             println!("URL: {}", file.url);
             println!("Size: {} bytes ({} MB)", file.size, file.size / 1_048_576);
-            match &file.file_type {
-                FileType::Image(format) => println!("Type: Image ({})", format),
-                FileType::Video(format) => println!("Type: Video ({})", format),
-                FileType::Audio(format) => println!("Type: Audio ({})", format),
-                FileType::Document(format) => println!("Type: Document ({})", format),
-                FileType::Other(ext) => println!("Type: Other ({})", ext),
-                FileType::Directory => println!("Type: Directory"),
+            println!("Size source: {}", file.size_source.label());
+            if let Some(modified) = &file.modified {
+                println!("Modified: {}", modified);
+            }
+            if let Some(content_type) = &file.content_type {
+                println!("Content type: {}", content_type);
+            }
+            if let Some(etag) = &file.etag {
+                println!("ETag: {}", etag);
+            }
+            println!("Type: {}", file.effective_type().label());
+            if file.type_mismatch {
+                println!("Named as: {}", file.file_type.label());
+            }
+            if let Some((width, height)) = file.dimensions() {
+                println!("Dimensions: {}x{}", width, height);
+            }
+            if let Some(media) = &file.media {
+                if let Some(duration) = media.duration {
+                    println!("Duration: {}", format_duration(duration));
+                }
+                let codecs: Vec<&str> = media.codecs().map(String::as_str).collect();
+                if !codecs.is_empty() {
+                    println!("Codecs: {}", codecs.join(", "));
+                }
+            }
+            if let Some(document) = &file.document {
+                if let Some(title) = &document.title {
+                    println!("Title: {}", title);
+                }
+                if let Some(author) = &document.author {
+                    println!("Author: {}", author);
+                }
+                if let Some(pages) = document.pages {
+                    println!("Pages: {}", pages);
+                }
             }
         }
 
@@ -108,12 +402,80 @@ impl DirectoryReport {
                 println!("{}", file);
             }
         }
+
+        if !self.nsfw_unchecked.is_empty() {
+            println!("\n=== Not Checked for NSFW Content ===");
+            for file in &self.nsfw_unchecked {
+                println!("{}", file);
+            }
+        }
+
+        if !self.external_references.is_empty() {
+            println!("\n=== External References ===");
+            for link in &self.external_references {
+                println!("{}", link);
+            }
+        }
+
+        if !self.robots_excluded.is_empty() {
+            println!("\n=== Excluded by robots.txt ===");
+            for path in &self.robots_excluded {
+                println!("{}", path);
+            }
+        }
+
+        if !self.type_mismatches.is_empty() {
+            println!("\n=== Type Mismatches ===");
+            for (url, types) in &self.type_mismatches {
+                println!("{}: {}", url, types);
+            }
+        }
+
+        if !self.geotagged_images.is_empty() {
+            println!("\n=== Geotagged Images ===");
+            for (url, position) in &self.geotagged_images {
+                println!("{}: {}", url, position);
+            }
+        }
+
+        if !self.archives.is_empty() {
+            println!("\n=== Archive Contents ===");
+            for (url, entries) in &self.archives {
+                println!("{} ({} files)", url, entries.len());
+                for entry in entries {
+                    println!("  {} ({} bytes)", entry.name, entry.size);
+                }
+            }
+        }
+
+        if !self.failed_directories.is_empty() {
+            println!("\n=== Failed Directories ===");
+            for (dir, error) in &self.failed_directories {
+                println!("{}: {}", dir, error);
+            }
+        }
+
+        if !self.unvisited_directories.is_empty() {
+            println!("\n=== Unvisited Directories ===");
+            for dir in &self.unvisited_directories {
+                println!("{}", dir);
+            }
+        }
     }
 
     pub fn save_to_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
         let mut file = fs::File::create(path)?;
 
-        writeln!(file, "# Directory Scan Report")?;
+        if self.incomplete {
+            writeln!(file, "# Directory Scan Report (incomplete)")?;
+            writeln!(
+                file,
+                "\n> The scan was interrupted; {} directories were not visited.",
+                self.unvisited_directories.len()
+            )?;
+        } else {
+            writeln!(file, "# Directory Scan Report")?;
+        }
         writeln!(file, "\n## General Statistics")?;
         writeln!(file, "- Total files: {}", self.total_files)?;
         writeln!(file, "- Total directories: {}", self.total_directories)?;
@@ -123,14 +485,73 @@ impl DirectoryReport {
             self.total_size,
             self.total_size / 1_048_576
         )?;
+        if self.unknown_size_count > 0 {
+            writeln!(
+                file,
+                "- Files with unknown size: {}",
+                self.unknown_size_count
+            )?;
+        }
+        writeln!(
+            file,
+            "- Duplicate links suppressed: {}",
+            self.duplicates_suppressed
+        )?;
+        writeln!(
+            file,
+            "- Directory loops suppressed: {}",
+            self.loops_suppressed
+        )?;
+        if self.filtered_count > 0 {
+            writeln!(
+                file,
+                "- Entries excluded by filters: {}",
+                self.filtered_count
+            )?;
+        }
+        if !self.robots_excluded.is_empty() {
+            writeln!(
+                file,
+                "- Entries excluded by robots.txt: {}",
+                self.robots_excluded.len()
+            )?;
+        }
+        if !self.type_mismatches.is_empty() {
+            writeln!(file, "- Type mismatches: {}", self.type_mismatches.len())?;
+        }
+        if !self.geotagged_images.is_empty() {
+            writeln!(file, "- Geotagged images: {}", self.geotagged_images.len())?;
+        }
+        if !self.archives.is_empty() {
+            let (count, size) = self.archive_totals();
+            writeln!(
+                file,
+                "- Archives listed: {} ({} files, {} bytes uncompressed)",
+                self.archives.len(),
+                count,
+                size
+            )?;
+        }
+        if self.total_media_duration > 0.0 {
+            writeln!(
+                file,
+                "- Total media duration: {}",
+                format_duration(self.total_media_duration)
+            )?;
+        }
 
         writeln!(file, "\n## File Type Breakdown")?;
-        writeln!(file, "- Images: {} files", self.image_count)?;
-        writeln!(file, "- Videos: {} files", self.video_count)?;
-        writeln!(file, "- Audio: {} files", self.audio_count)?;
-        writeln!(file, "- Documents: {} files", self.document_count)?;
-        writeln!(file, "- Other: {} files", self.other_count)?;
+        for (category, count) in self.categories() {
+            writeln!(file, "- {}: {} files", category, count)?;
+        }
         writeln!(file, "- NSFW content: {} files", self.nsfw_count)?;
+        if !self.nsfw_unchecked.is_empty() {
+            writeln!(
+                file,
+                "- Not checked for NSFW content: {} files",
+                self.nsfw_unchecked.len()
+            )?;
+        }
 
         writeln!(file, "\n## Format Distribution")?;
         let mut formats: Vec<_> = self.files_by_type.iter().collect();
@@ -139,6 +560,27 @@ impl DirectoryReport {
             writeln!(file, "- {}: {} files", format, count)?;
         }
 
+        if !self.images_by_resolution.is_empty() {
+            writeln!(file, "\n## Image Resolutions")?;
+            for (class, count) in self.resolutions() {
+                writeln!(file, "- {}: {} files", class, count)?;
+            }
+        }
+
+        if !self.media_by_codec.is_empty() {
+            writeln!(file, "\n## Media Codecs")?;
+            for (codec, count) in self.codecs() {
+                writeln!(file, "- {}: {} files", codec, count)?;
+            }
+        }
+
+        if !self.documents_by_author.is_empty() {
+            writeln!(file, "\n## Document Authors")?;
+            for (author, count) in self.authors() {
+                writeln!(file, "- {}: {} files", author, count)?;
+            }
+        }
+
         if let Some(file_info) = &self.largest_file {
             writeln!(file, "\n## Largest File")?;
             writeln!(file, "- Name: {}", file_info.name)?;
@@ -149,13 +591,42 @@ impl DirectoryReport {
                 file_info.size,
                 file_info.size / 1_048_576
             )?;
-            match &file_info.file_type {
-                FileType::Image(format) => writeln!(file, "- Type: Image ({})", format)?,
-                FileType::Video(format) => writeln!(file, "- Type: Video ({})", format)?,
-                FileType::Audio(format) => writeln!(file, "- Type: Audio ({})", format)?,
-                FileType::Document(format) => writeln!(file, "- Type: Document ({})", format)?,
-                FileType::Other(ext) => writeln!(file, "- Type: Other ({})", ext)?,
-                FileType::Directory => writeln!(file, "- Type: Directory")?,
+            writeln!(file, "- Size source: {}", file_info.size_source.label())?;
+            if let Some(modified) = &file_info.modified {
+                writeln!(file, "- Modified: {}", modified)?;
+            }
+            if let Some(content_type) = &file_info.content_type {
+                writeln!(file, "- Content type: {}", content_type)?;
+            }
+            if let Some(etag) = &file_info.etag {
+                writeln!(file, "- ETag: {}", etag)?;
+            }
+            writeln!(file, "- Type: {}", file_info.effective_type().label())?;
+            if file_info.type_mismatch {
+                writeln!(file, "- Named as: {}", file_info.file_type.label())?;
+            }
+            if let Some((width, height)) = file_info.dimensions() {
+                writeln!(file, "- Dimensions: {}x{}", width, height)?;
+            }
+            if let Some(media) = &file_info.media {
+                if let Some(duration) = media.duration {
+                    writeln!(file, "- Duration: {}", format_duration(duration))?;
+                }
+                let codecs: Vec<&str> = media.codecs().map(String::as_str).collect();
+                if !codecs.is_empty() {
+                    writeln!(file, "- Codecs: {}", codecs.join(", "))?;
+                }
+            }
+            if let Some(document) = &file_info.document {
+                if let Some(title) = &document.title {
+                    writeln!(file, "- Title: {}", title)?;
+                }
+                if let Some(author) = &document.author {
+                    writeln!(file, "- Author: {}", author)?;
+                }
+                if let Some(pages) = document.pages {
+                    writeln!(file, "- Pages: {}", pages)?;
+                }
             }
         }
 
@@ -165,6 +636,141 @@ impl DirectoryReport {
             writeln!(file, "- Size: {} bytes ({} MB)", size, size / 1_048_576)?;
         }
 
+        if !self.nsfw_unchecked.is_empty() {
+            writeln!(file, "\n## Not Checked for NSFW Content")?;
+            for url in &self.nsfw_unchecked {
+                writeln!(file, "- {}", url)?;
+            }
+        }
+
+        if !self.external_references.is_empty() {
+            writeln!(file, "\n## External References")?;
+            for link in &self.external_references {
+                writeln!(file, "- {}", link)?;
+            }
+        }
+
+        if !self.robots_excluded.is_empty() {
+            writeln!(file, "\n## Excluded by robots.txt")?;
+            for path in &self.robots_excluded {
+                writeln!(file, "- {}", path)?;
+            }
+        }
+
+        if !self.type_mismatches.is_empty() {
+            writeln!(file, "\n## Type Mismatches")?;
+            for (url, types) in &self.type_mismatches {
+                writeln!(file, "- {}: {}", url, types)?;
+            }
+        }
+
+        if !self.geotagged_images.is_empty() {
+            writeln!(file, "\n## Geotagged Images")?;
+            for (url, position) in &self.geotagged_images {
+                writeln!(file, "- {}: {}", url, position)?;
+            }
+        }
+
+        if !self.archives.is_empty() {
+            writeln!(file, "\n## Archive Contents")?;
+            for (url, entries) in &self.archives {
+                writeln!(file, "- {} ({} files)", url, entries.len())?;
+                for entry in entries {
+                    writeln!(file, "  - {} ({} bytes)", entry.name, entry.size)?;
+                }
+            }
+        }
+
+        if !self.failed_directories.is_empty() {
+            writeln!(file, "\n## Failed Directories")?;
+            for (dir, error) in &self.failed_directories {
+                writeln!(file, "- {}: {}", dir, error)?;
+            }
+        }
+
+        if !self.unvisited_directories.is_empty() {
+            writeln!(file, "\n## Unvisited Directories")?;
+            for dir in &self.unvisited_directories {
+                writeln!(file, "- {}", dir)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the report and every file with its metadata as JSON.
+    pub fn save_json(&self, files: &[FileInfo], path: &str) -> Result<(), Box<dyn Error>> {
+        let files: Vec<&FileInfo> = files
+            .iter()
+            .filter(|f| *f.effective_type() != FileType::Directory)
+            .collect();
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(
+            file,
+            &JsonReport {
+                report: self,
+                files,
+            },
+        )?;
         Ok(())
     }
 }
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    report: &'a DirectoryReport,
+    files: Vec<&'a FileInfo>,
+}
+
+const CSV_HEADER: &str = "url,name,size,modified,category,format,content_type,width,height,duration,title,author,creator,producer,pages,created,document_modified,nsfw_unchecked";
+
+/// Quotes a CSV field when it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes one CSV row per file. Title and author come from document
+/// metadata, or else from media tags.
+pub fn save_csv(files: &[FileInfo], path: &str) -> Result<(), Box<dyn Error>> {
+    let mut file = fs::File::create(path)?;
+    writeln!(file, "{}", CSV_HEADER)?;
+    for file_info in files {
+        let file_type = file_info.effective_type();
+        if *file_type == FileType::Directory {
+            continue;
+        }
+        let document = file_info.document.clone().unwrap_or_default();
+        let media = file_info.media.clone().unwrap_or_default();
+        let dimensions = file_info.dimensions();
+        let fields = [
+            Some(file_info.url.clone()),
+            Some(file_info.name.clone()),
+            (file_info.size_source != SizeSource::Unknown).then(|| file_info.size.to_string()),
+            file_info.modified.clone(),
+            file_type.category().map(str::to_string),
+            file_type.format().map(str::to_string),
+            file_info.content_type.clone(),
+            dimensions.map(|(width, _)| width.to_string()),
+            dimensions.map(|(_, height)| height.to_string()),
+            media.duration.map(|d| format!("{:.1}", d)),
+            document.title.or(media.title),
+            document.author.or(media.artist),
+            document.creator,
+            document.producer,
+            document.pages.map(|p| p.to_string()),
+            document.created,
+            document.modified,
+            file_info.nsfw_unchecked.then(|| "true".to_string()),
+        ];
+        let row: Vec<String> = fields
+            .iter()
+            .map(|field| csv_field(field.as_deref().unwrap_or_default()))
+            .collect();
+        writeln!(file, "{}", row.join(","))?;
+    }
+    Ok(())
+}
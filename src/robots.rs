@@ -0,0 +1,271 @@
+use crate::http::HttpClient;
+use crate::urls;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// Product token matched against `User-agent` lines.
+const AGENT: &str = env!("CARGO_PKG_NAME");
+
+struct Rule {
+    allow: bool,
+    /// Length of the original pattern; the longest matching rule wins.
+    specificity: usize,
+    pattern: Regex,
+}
+
+/// The rules of one robots.txt that apply to this crawler.
+struct RobotsRules {
+    rules: Vec<Rule>,
+}
+
+/// Translates a robots.txt path pattern (`*` wildcards, trailing `$`
+/// anchor) into an anchored regex.
+fn compile_pattern(pattern: &str) -> Option<Regex> {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(stripped) => (stripped, true),
+        None => (pattern, false),
+    };
+    let body: Vec<String> = pattern.split('*').map(regex::escape).collect();
+    let mut source = format!("^{}", body.join(".*"));
+    if anchored {
+        source.push('$');
+    }
+    Regex::new(&source).ok()
+}
+
+impl RobotsRules {
+    fn allow_all() -> Self {
+        RobotsRules { rules: Vec::new() }
+    }
+
+    fn disallow_all() -> Self {
+        RobotsRules {
+            rules: vec![Rule {
+                allow: false,
+                specificity: 1,
+                pattern: compile_pattern("/").unwrap(),
+            }],
+        }
+    }
+
+    /// Parses a robots.txt body following RFC 9309. The groups naming this
+    /// crawler are used if there are any, otherwise the `*` groups.
+    fn parse(body: &str) -> Self {
+        let mut specific = Vec::new();
+        let mut wildcard = Vec::new();
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+
+            match key.as_str() {
+                "user-agent" => {
+                    // A user-agent line after rules starts a new group.
+                    if in_rules {
+                        agents.clear();
+                        in_rules = false;
+                    }
+                    agents.push(value.to_ascii_lowercase());
+                }
+                "allow" | "disallow" => {
+                    in_rules = true;
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let Some(pattern) = compile_pattern(value) else {
+                        continue;
+                    };
+                    let rule = Rule {
+                        allow: key == "allow",
+                        specificity: value.len(),
+                        pattern,
+                    };
+                    if agents.iter().any(|a| a == AGENT) {
+                        specific.push(rule);
+                    } else if agents.iter().any(|a| a == "*") {
+                        wildcard.push(rule);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        RobotsRules {
+            rules: if specific.is_empty() {
+                wildcard
+            } else {
+                specific
+            },
+        }
+    }
+
+    /// Checks a URL path (including any query). The most specific matching
+    /// rule decides; `Allow` wins a tie.
+    fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| rule.pattern.is_match(path))
+            .max_by_key(|rule| (rule.specificity, rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+/// Fetches and caches robots.txt per host. When disabled every URL is
+/// allowed and nothing is fetched.
+pub struct Robots {
+    enabled: bool,
+    hosts: Mutex<HashMap<String, Arc<RobotsRules>>>,
+}
+
+impl Robots {
+    pub fn new(enabled: bool) -> Self {
+        Robots {
+            enabled,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn allows(&self, url: &str, client: &HttpClient) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let Ok(parsed) = Url::parse(url) else {
+            return true;
+        };
+        let Some(host) = urls::host_key(&parsed) else {
+            return true;
+        };
+
+        let cached = self.hosts.lock().unwrap().get(&host).cloned();
+        let rules = match cached {
+            Some(rules) => rules,
+            None => {
+                let rules = Arc::new(fetch(&parsed, client));
+                self.hosts
+                    .lock()
+                    .unwrap()
+                    .entry(host)
+                    .or_insert(rules)
+                    .clone()
+            }
+        };
+
+        let path = match parsed.query() {
+            Some(query) => format!("{}?{}", parsed.path(), query),
+            None => parsed.path().to_string(),
+        };
+        rules.allows(&path)
+    }
+}
+
+/// Missing robots.txt files (4xx) allow everything; server errors and
+/// unreachable hosts disallow everything, as RFC 9309 requires.
+fn fetch(url: &Url, client: &HttpClient) -> RobotsRules {
+    let mut robots_url = url.clone();
+    robots_url.set_path("/robots.txt");
+    robots_url.set_query(None);
+    robots_url.set_fragment(None);
+
+    match client.get(robots_url.as_str()) {
+        Ok(response) if response.status().is_success() => match response.text() {
+            Ok(body) => RobotsRules::parse(&body),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", robots_url, e);
+                RobotsRules::disallow_all()
+            }
+        },
+        Ok(response) if response.status().is_client_error() => RobotsRules::allow_all(),
+        Ok(response) => {
+            eprintln!("Failed to fetch {}: {}", robots_url, response.status());
+            RobotsRules::disallow_all()
+        }
+        Err(e) => {
+            eprintln!("Failed to fetch {}: {}", robots_url, e);
+            RobotsRules::disallow_all()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Auth, AuthSettings};
+    use mockito::Server;
+
+    fn client() -> HttpClient {
+        HttpClient::for_tests(Auth::new(&AuthSettings::default()).unwrap())
+    }
+
+    #[test]
+    fn prefers_groups_naming_this_crawler() {
+        let rules = RobotsRules::parse(&format!(
+            "User-agent: *\nDisallow: /\n\nUser-agent: Googlebot\nUser-agent: {}\nDisallow: /private/\n",
+            AGENT
+        ));
+        assert!(rules.allows("/pub/"));
+        assert!(!rules.allows("/private/a.txt"));
+    }
+
+    #[test]
+    fn falls_back_to_wildcard_group() {
+        let rules = RobotsRules::parse(
+            "# comment\nUser-agent: Googlebot\nDisallow: /\n\nuser-agent: *\ndisallow: /tmp/ # scratch\nDisallow:\n",
+        );
+        assert!(rules.allows("/pub/"));
+        assert!(!rules.allows("/tmp/x"));
+    }
+
+    #[test]
+    fn most_specific_rule_wins() {
+        let rules = RobotsRules::parse(
+            "User-agent: *\nDisallow: /pub/\nAllow: /pub/linux/\nDisallow: /*.iso$\nAllow: /pub/a\nDisallow: /pub/a\n",
+        );
+        assert!(!rules.allows("/pub/bsd/"));
+        assert!(rules.allows("/pub/linux/"));
+        assert!(!rules.allows("/netinst.iso"));
+        assert!(rules.allows("/netinst.iso.sig"));
+        // The longer pattern wins, wildcards or not.
+        assert!(rules.allows("/pub/linux/netinst.iso"));
+        // Allow wins a tie.
+        assert!(rules.allows("/pub/a"));
+    }
+
+    #[test]
+    fn caches_rules_per_host() {
+        let mut server = Server::new();
+        let robots = server
+            .mock("GET", "/robots.txt")
+            .with_body("User-agent: *\nDisallow: /private/\n")
+            .expect(1)
+            .create();
+        let checker = Robots::new(true);
+        let client = client();
+        assert!(checker.allows(&format!("{}/pub/", server.url()), &client));
+        assert!(!checker.allows(&format!("{}/private/", server.url()), &client));
+        robots.assert();
+    }
+
+    #[test]
+    fn treats_missing_and_failing_files_differently() {
+        let mut server = Server::new();
+        server.mock("GET", "/robots.txt").with_status(404).create();
+        assert!(Robots::new(true).allows(&format!("{}/a/", server.url()), &client()));
+
+        let mut server = Server::new();
+        server.mock("GET", "/robots.txt").with_status(503).create();
+        assert!(!Robots::new(true).allows(&format!("{}/a/", server.url()), &client()));
+    }
+
+    #[test]
+    fn allows_everything_when_disabled() {
+        let rules = Robots::new(false);
+        assert!(rules.allows("http://127.0.0.1:9/private/", &client()));
+    }
+}
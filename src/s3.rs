@@ -0,0 +1,337 @@
+use crate::backend::{Backend, HttpBackend, RawListing};
+use crate::http::HttpClient;
+use crate::listing::ListingEntry;
+use crate::probe::Probe;
+use crate::urls;
+use reqwest::header::SERVER;
+use roxmltree::{Document, Node};
+use std::error::Error;
+use url::Url;
+
+/// One page of a `ListObjectsV2` response.
+struct ListPage {
+    prefixes: Vec<String>,
+    objects: Vec<S3Object>,
+    next_token: Option<String>,
+}
+
+struct S3Object {
+    key: String,
+    size: Option<u64>,
+    modified: Option<String>,
+    etag: Option<String>,
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|n| n.tag_name().name() == name)
+        .and_then(|n| n.text())
+        .map(str::to_string)
+}
+
+/// Parses a `<ListBucketResult>` document. Elements are matched by local
+/// name because S3 and its clones do not agree on the namespace.
+fn parse_page(body: &str) -> Result<ListPage, Box<dyn Error>> {
+    let document = Document::parse(body)?;
+    let root = document.root_element();
+    if root.tag_name().name() != "ListBucketResult" {
+        return Err(format!("unexpected <{}> response", root.tag_name().name()).into());
+    }
+
+    let prefixes = root
+        .children()
+        .filter(|n| n.tag_name().name() == "CommonPrefixes")
+        .filter_map(|n| child_text(n, "Prefix"))
+        .collect();
+    let objects = root
+        .children()
+        .filter(|n| n.tag_name().name() == "Contents")
+        .filter_map(|n| {
+            Some(S3Object {
+                key: child_text(n, "Key")?,
+                size: child_text(n, "Size").and_then(|s| s.parse().ok()),
+                modified: child_text(n, "LastModified"),
+                etag: child_text(n, "ETag").map(|e| e.trim_matches('"').to_string()),
+            })
+        })
+        .collect();
+    let truncated = child_text(root, "IsTruncated").as_deref() == Some("true");
+    let next_token = child_text(root, "NextContinuationToken").filter(|_| truncated);
+
+    Ok(ListPage {
+        prefixes,
+        objects,
+        next_token,
+    })
+}
+
+/// Requests one page of the keys directly under `prefix`.
+fn list_page(
+    bucket_url: &str,
+    prefix: &str,
+    token: Option<&str>,
+    client: &HttpClient,
+) -> Result<ListPage, Box<dyn Error>> {
+    let mut request_url = Url::parse(bucket_url)?;
+    {
+        let mut query = request_url.query_pairs_mut();
+        query
+            .append_pair("list-type", "2")
+            .append_pair("delimiter", "/")
+            .append_pair("prefix", prefix);
+        if let Some(token) = token {
+            query.append_pair("continuation-token", token);
+        }
+    }
+
+    let response = client.get(request_url.as_str())?;
+    if !response.status().is_success() {
+        return Err(format!("Failed to list {}: {}", request_url, response.status()).into());
+    }
+    parse_page(&response.text()?)
+}
+
+/// Whether the server answers `url` with headers that S3 and its clones
+/// send: `x-amz-*` or `Server: AmazonS3`.
+fn is_s3_response(url: &str, client: &HttpClient) -> bool {
+    let Ok(response) = client.head(url) else {
+        return false;
+    };
+    let headers = response.headers();
+    headers
+        .keys()
+        .any(|name| name.as_str().starts_with("x-amz-"))
+        || headers
+            .get(SERVER)
+            .and_then(|server| server.to_str().ok())
+            .is_some_and(|server| server.contains("AmazonS3"))
+}
+
+/// Looks for a bucket that `url` lies in, trying virtual-hosted style
+/// (`https://bucket.host/`) and then path style (`https://host/bucket/`).
+/// Returns the bucket's root URL. Unless `force` is set, only servers that
+/// identify as S3 are asked; candidates disallowed by robots.txt never are.
+pub fn detect_bucket(url: &str, http: &HttpBackend, force: bool) -> Option<String> {
+    if !http.allows(url) || !(force || is_s3_response(url, http.client())) {
+        return None;
+    }
+    let parsed = Url::parse(url).ok()?;
+    let mut candidates = vec![parsed.join("/").ok()?];
+    if let Some(bucket) = parsed
+        .path_segments()
+        .and_then(|mut segments| segments.next())
+        .filter(|s| !s.is_empty())
+    {
+        candidates.push(parsed.join(&format!("/{}/", bucket)).ok()?);
+    }
+
+    candidates.into_iter().map(String::from).find(|candidate| {
+        let Some(prefix) = prefix_within(candidate, url) else {
+            return false;
+        };
+        http.allows(candidate) && list_page(candidate, &prefix, None, http.client()).is_ok()
+    })
+}
+
+/// The key prefix that directory `url` stands for inside the bucket.
+fn prefix_within(bucket_url: &str, url: &str) -> Option<String> {
+    let bucket_path = urls::decoded_path(bucket_url);
+    let mut path = urls::decoded_path(url);
+    if !path.ends_with('/') {
+        path.push('/');
+    }
+    path.strip_prefix(&bucket_path).map(str::to_string)
+}
+
+/// Lists an S3-compatible bucket with `ListObjectsV2`. Common prefixes
+/// become directories and keys become files. Downloads and robots.txt go
+/// through the plain HTTP backend.
+pub struct S3Backend {
+    http: HttpBackend,
+    bucket_url: String,
+}
+
+impl S3Backend {
+    pub fn new(http: HttpBackend, bucket_url: String) -> Self {
+        S3Backend { http, bucket_url }
+    }
+}
+
+impl Backend for S3Backend {
+    fn list(&self, url: &str) -> Result<RawListing, Box<dyn Error>> {
+        let prefix = prefix_within(&self.bucket_url, url)
+            .ok_or_else(|| format!("{} is outside bucket {}", url, self.bucket_url))?;
+
+        let mut entries = Vec::new();
+        let mut token = None;
+        let mut pages = 0;
+        loop {
+            let page = list_page(
+                &self.bucket_url,
+                &prefix,
+                token.as_deref(),
+                self.http.client(),
+            )?;
+            pages += 1;
+
+            for directory in page.prefixes {
+                let Some(name) = directory.strip_prefix(&prefix) else {
+                    continue;
+                };
+                let name = name.trim_end_matches('/');
+                if name.is_empty() {
+                    continue;
+                }
+                entries.push(ListingEntry {
+                    name: name.to_string(),
+                    href: format!("{}/", urls::encode_segment(name)),
+                    size: None,
+                    modified: None,
+                    content_type: None,
+                    etag: None,
+                    is_directory: true,
+                });
+            }
+            for object in page.objects {
+                // Keys ending in `/` are folder placeholders, and the key
+                // equal to the prefix is the folder itself.
+                let Some(name) = object.key.strip_prefix(&prefix) else {
+                    continue;
+                };
+                if name.is_empty() || name.ends_with('/') {
+                    continue;
+                }
+                entries.push(ListingEntry {
+                    name: name.to_string(),
+                    href: urls::encode_segment(name),
+                    size: object.size,
+                    modified: object.modified,
+                    content_type: None,
+                    etag: object.etag,
+                    is_directory: false,
+                });
+            }
+
+            match page.next_token {
+                Some(next) if token.as_ref() != Some(&next) => token = Some(next),
+                _ => break,
+            }
+        }
+
+        println!(
+            "Parsed {} entries from {} (S3 listing, {} pages)",
+            entries.len(),
+            url,
+            pages
+        );
+        Ok((urls::canonicalize(url, true), entries))
+    }
+
+    fn probe(&self, url: &str) -> Result<Probe, Box<dyn Error>> {
+        self.http.probe(url)
+    }
+
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.http.fetch(url)
+    }
+
+    fn fetch_range(
+        &self,
+        url: &str,
+        offset: u64,
+        length: usize,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.http.fetch_range(url, offset, length)
+    }
+
+    fn fetch_tail(&self, url: &str, length: u64) -> Result<(u64, Vec<u8>), Box<dyn Error>> {
+        self.http.fetch_tail(url, length)
+    }
+
+    fn allows(&self, url: &str) -> bool {
+        self.http.allows(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Auth, AuthSettings};
+    use mockito::{Matcher, Server};
+
+    const PAGE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <Name>bucket</Name>
+  <Prefix>dir/</Prefix>
+  <IsTruncated>true</IsTruncated>
+  <NextContinuationToken>next</NextContinuationToken>
+  <Contents><Key>dir/a.txt</Key><Size>12</Size><ETag>"abc"</ETag></Contents>
+  <CommonPrefixes><Prefix>dir/sub/</Prefix></CommonPrefixes>
+</ListBucketResult>"#;
+
+    fn http(respect_robots: bool) -> HttpBackend {
+        let auth = Auth::new(&AuthSettings::default()).unwrap();
+        HttpBackend::new(HttpClient::for_tests(auth), respect_robots)
+    }
+
+    fn list_request(server: &mut Server, path: &str) -> mockito::Mock {
+        server
+            .mock("GET", path)
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("list-type".into(), "2".into()),
+                Matcher::UrlEncoded("prefix".into(), "dir/".into()),
+            ]))
+            .with_body(PAGE)
+    }
+
+    #[test]
+    fn parses_list_page() {
+        let page = parse_page(PAGE).unwrap();
+        assert_eq!(page.prefixes, ["dir/sub/"]);
+        assert_eq!(page.objects[0].key, "dir/a.txt");
+        assert_eq!(page.objects[0].size, Some(12));
+        assert_eq!(page.objects[0].etag.as_deref(), Some("abc"));
+        assert_eq!(page.next_token.as_deref(), Some("next"));
+        assert!(parse_page("<Error><Code>AccessDenied</Code></Error>").is_err());
+    }
+
+    #[test]
+    fn detects_path_style_bucket_from_headers() {
+        let mut server = Server::new();
+        server
+            .mock("HEAD", "/bucket/dir/")
+            .with_header("x-amz-request-id", "1")
+            .create();
+        let list = list_request(&mut server, "/bucket/").create();
+        let url = format!("{}/bucket/dir/", server.url());
+        let bucket = detect_bucket(&url, &http(false), false);
+        assert_eq!(bucket, Some(format!("{}/bucket/", server.url())));
+        list.assert();
+    }
+
+    #[test]
+    fn leaves_other_servers_alone() {
+        let mut server = Server::new();
+        server
+            .mock("HEAD", "/bucket/dir/")
+            .with_header("server", "nginx")
+            .create();
+        let list = list_request(&mut server, "/bucket/").expect(0).create();
+        let url = format!("{}/bucket/dir/", server.url());
+        assert_eq!(detect_bucket(&url, &http(false), false), None);
+        list.assert();
+    }
+
+    #[test]
+    fn skips_candidates_disallowed_by_robots() {
+        let mut server = Server::new();
+        server
+            .mock("GET", "/robots.txt")
+            .with_body("User-agent: *\nDisallow: /\nAllow: /bucket/dir/\n")
+            .create();
+        let list = list_request(&mut server, "/bucket/").expect(0).create();
+        let url = format!("{}/bucket/dir/", server.url());
+        assert_eq!(detect_bucket(&url, &http(true), true), None);
+        list.assert();
+    }
+}
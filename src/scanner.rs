@@ -1,198 +1,655 @@
+use crate::archive;
+use crate::backend::Backend;
+use crate::checkpoint::{Checkpoint, ScanSettings};
+use crate::crawler::{self, Listings, VisitedSet};
+use crate::document;
+use crate::filetypes::{self, FileTypes};
+use crate::filter::Filters;
+use crate::imagemeta;
+use crate::listing::ListingEntry;
+use crate::media;
 use crate::nsfwcheck::NSFWDetector;
-use crate::report::{DirectoryReport, FileInfo, FileType};
-use reqwest::blocking::Client;
-use scraper::{Html, Selector};
+use crate::probe::Probe;
+use crate::report::{DirectoryReport, FileInfo, FileType, SizeSource};
+use crate::scope::Scope;
+use crate::sniff;
+use crate::urls;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::path::Path;
-
-fn get_file_type(filename: &str) -> FileType {
-    let extension = Path::new(filename)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("")
-        .to_lowercase();
-
-    match extension.as_str() {
-        "jpg" | "jpeg" => FileType::Image("JPEG".to_string()),
-        "png" => FileType::Image("PNG".to_string()),
-        "gif" => FileType::Image("GIF".to_string()),
-        "webp" => FileType::Image("WebP".to_string()),
-        "bmp" => FileType::Image("BMP".to_string()),
-        "svg" => FileType::Image("SVG".to_string()),
-        "tiff" | "tif" => FileType::Image("TIFF".to_string()),
-        "mp4" => FileType::Video("MP4".to_string()),
-        "webm" => FileType::Video("WebM".to_string()),
-        "avi" => FileType::Video("AVI".to_string()),
-        "mov" => FileType::Video("QuickTime".to_string()),
-        "mkv" => FileType::Video("Matroska".to_string()),
-        "flv" => FileType::Video("Flash".to_string()),
-        "wmv" => FileType::Video("Windows Media".to_string()),
-        "mp3" => FileType::Audio("MP3".to_string()),
-        "wav" => FileType::Audio("WAV".to_string()),
-        "ogg" => FileType::Audio("OGG".to_string()),
-        "flac" => FileType::Audio("FLAC".to_string()),
-        "aac" => FileType::Audio("AAC".to_string()),
-        "pdf" => FileType::Document("PDF".to_string()),
-        "doc" | "docx" => FileType::Document("Word".to_string()),
-        "xls" | "xlsx" => FileType::Document("Excel".to_string()),
-        "ppt" | "pptx" => FileType::Document("PowerPoint".to_string()),
-        "txt" => FileType::Document("Text".to_string()),
-        "md" => FileType::Document("Markdown".to_string()),
-        "" => FileType::Directory,
-        _ => FileType::Other(extension),
+use std::time::Duration;
+
+/// Formats whose extension is shared by audio and video files.
+const AMBIGUOUS_FORMATS: &[&str] = &["OGG", "WebM"];
+
+/// Images larger than this are not downloaded for the NSFW check. Their
+/// headers are read with range requests instead.
+const MAX_NSFW_IMAGE: u64 = 32 * 1024 * 1024;
+
+/// Whether the name leaves the kind of file open: no extension, an unknown
+/// one, or one used for both audio and video.
+fn is_type_unclear(file_type: &FileType) -> bool {
+    match file_type.category() {
+        None | Some(filetypes::OTHER) => true,
+        Some(filetypes::AUDIO | filetypes::VIDEO) => {
+            AMBIGUOUS_FORMATS.contains(&file_type.format().unwrap_or(""))
+        }
+        _ => false,
     }
 }
 
-pub fn scan_directory(
-    url: &str,
-    depth: u32,
-    max_depth: u32,
-    client: &Client,
-    nsfw_detector: &NSFWDetector,
-) -> Result<(DirectoryReport, Vec<FileInfo>), Box<dyn Error>> {
-    if depth > max_depth {
-        return Ok((DirectoryReport::new(), vec![]));
+/// Classifies a MIME type, keeping the MIME string as the format. Generic
+/// types such as `application/octet-stream` say nothing about the file.
+fn type_from_mime(mime: &str) -> Option<FileType> {
+    let mime = mime.split(';').next()?.trim().to_ascii_lowercase();
+    let (kind, subtype) = mime.split_once('/')?;
+    let office = subtype == "pdf"
+        || subtype == "msword"
+        || subtype == "rtf"
+        || subtype.starts_with("vnd.ms-")
+        || subtype.starts_with("vnd.openxmlformats-officedocument")
+        || subtype.starts_with("vnd.oasis.opendocument");
+
+    let category = match kind {
+        "image" => filetypes::IMAGE,
+        "video" => filetypes::VIDEO,
+        "audio" => filetypes::AUDIO,
+        "text" if subtype == "html" => filetypes::OTHER,
+        "text" => filetypes::DOCUMENT,
+        "application" if office => filetypes::DOCUMENT,
+        "application" if matches!(subtype, "octet-stream" | "binary" | "unknown") => return None,
+        "application" => filetypes::OTHER,
+        _ => return None,
+    };
+    Some(FileType::file(category, &mime))
+}
+
+/// Classifies an entry by its name, using the server's MIME type where the
+/// name leaves the type unclear and the MIME type is more specific.
+fn classify(
+    file_types: &FileTypes,
+    name: &str,
+    is_directory: bool,
+    content_type: Option<&str>,
+) -> FileType {
+    if is_directory {
+        return FileType::Directory;
+    }
+    let by_name = file_types.classify(name);
+    if !is_type_unclear(&by_name) {
+        return by_name;
+    }
+    match (by_name, content_type.and_then(type_from_mime)) {
+        (by_name, None) => by_name,
+        // Directory pages are HTML too.
+        (FileType::Directory, Some(by_mime)) if by_mime.format() == Some("text/html") => {
+            FileType::Directory
+        }
+        (FileType::Directory, Some(by_mime)) => by_mime,
+        (by_name, Some(by_mime)) if by_mime.is(filetypes::OTHER) => by_name,
+        (_, Some(by_mime)) => by_mime,
     }
+}
 
-    println!("Scanning directory: {} (depth: {})", url, depth);
+/// Whether a file is a ZIP archive whose contents can be listed.
+fn is_zip(name: &str, file_type: &FileType) -> bool {
+    name.to_ascii_lowercase().ends_with(".zip") || file_type.format() == Some("ZIP")
+}
 
-    let mut report = DirectoryReport::new();
-    let mut all_files = Vec::new();
+/// Whether a file is audio or video, whose headers hold media metadata.
+fn is_media(file_type: &FileType) -> bool {
+    file_type.is(filetypes::AUDIO) || file_type.is(filetypes::VIDEO)
+}
+
+/// Runs the NSFW check on a downloaded image, logging any failure.
+fn is_nsfw_image(
+    image_url: &str,
+    image: Result<Vec<u8>, Box<dyn Error>>,
+    detector: &NSFWDetector,
+) -> bool {
+    match image.and_then(|image| detector.is_nsfw(image_url, &image)) {
+        Ok(nsfw) => nsfw,
+        Err(e) => {
+            eprintln!("Failed to check NSFW for {}: {}", image_url, e);
+            false
+        }
+    }
+}
+
+/// Identifies a file from its first bytes. Text and markup do not turn an
+/// extensionless entry into a file, since generic listings also link to
+/// directories without a trailing slash.
+fn sniff_type(url: &str, claimed: &FileType, backend: &dyn Backend) -> Option<FileType> {
+    let data = match backend.fetch_prefix(url, sniff::SNIFF_LENGTH) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Failed to sniff type of {}: {}", url, e);
+            return None;
+        }
+    };
+    sniff::detect(&data)
+        .filter(|detected| *claimed != FileType::Directory || !sniff::is_markup_or_text(detected))
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub max_depth: u32,
+    /// Number of directories fetched in parallel.
+    pub concurrency: usize,
+    /// Upper bound on parallel directory fetches against a single host.
+    pub per_host_concurrency: usize,
+    /// Issue HEAD/Range requests for files whose size the listing omits.
+    pub probe_sizes: bool,
+    /// Fetch the first bytes of each file to identify it by content.
+    pub sniff_types: bool,
+    /// Read the central directory of ZIP archives to list their contents.
+    pub list_archives: bool,
+    /// Run the NSFW check on images inside listed archives.
+    pub check_archive_images: bool,
+    /// Read duration, codecs and tags from the headers of audio and video
+    /// files.
+    pub media_metadata: bool,
+    /// Read author, title and similar metadata of PDF and Office documents.
+    pub document_metadata: bool,
+    /// Follow links outside the starting path on the starting host.
+    pub allow_parent: bool,
+    /// Additional hosts whose links are followed instead of being reported
+    /// as external references.
+    pub allowed_hosts: Vec<String>,
+    /// Include/exclude rules applied before any per-file request.
+    pub filters: Filters,
+    /// Extension-to-category taxonomy from the `[file_types]` config.
+    pub file_types: FileTypes,
+    /// The options as given, recorded in checkpoints.
+    pub settings: ScanSettings,
+    /// File the crawl state is periodically written to.
+    pub checkpoint_path: Option<String>,
+    pub checkpoint_interval: Duration,
+}
+
+/// State shared by every crawler worker during one scan.
+pub struct ScanContext<'a> {
+    pub options: &'a ScanOptions,
+    pub backend: &'a dyn Backend,
+    pub nsfw_detector: &'a NSFWDetector,
+    pub scope: Scope,
+    pub visited: VisitedSet,
+}
+
+/// The entries of a single index page, in listing order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirectoryListing {
+    pub files: Vec<FileInfo>,
+    /// Canonical URLs of the files and directories on the page, in listing
+    /// order, with their position in `files`. Entries another listing
+    /// claimed first have none.
+    pub entries: Vec<(String, Option<usize>)>,
+    pub nsfw_files: HashSet<String>,
+    /// Out-of-scope links found on the page, recorded but not followed.
+    pub external_links: Vec<String>,
+    /// Entries dropped by the include/exclude filters.
+    pub filtered: usize,
+    /// In-scope entries the backend may not request (robots.txt).
+    pub robots_excluded: Vec<String>,
+    pub fingerprint: u64,
+}
 
-    let response = client.get(url).send()?;
-    if !response.status().is_success() {
-        return Err(format!("Failed to fetch {}: {}", url, response.status()).into());
+impl DirectoryListing {
+    pub fn subdirectories(&self) -> impl Iterator<Item = &str> {
+        self.files
+            .iter()
+            .filter(|f| *f.effective_type() == FileType::Directory)
+            .map(|f| f.url.as_str())
     }
+}
 
-    let body = response.text()?;
-    let document = Html::parse_document(&body);
-    let selector = Selector::parse("a").unwrap();
+/// Classifies the entries of one index page without descending into it.
+/// Entries already seen elsewhere in the crawl are dropped before any
+/// further requests are made for them.
+pub fn process_listing(
+    base: &str,
+    entries: Vec<ListingEntry>,
+    context: &ScanContext,
+) -> DirectoryListing {
+    let (options, backend) = (context.options, context.backend);
+    let mut files = Vec::new();
+    let mut listed = Vec::new();
+    let mut nsfw_files = HashSet::new();
+    let mut external_links = Vec::new();
+    let mut filtered = 0;
+    let mut robots_excluded = Vec::new();
 
-    for element in document.select(&selector) {
-        let href = match element.value().attr("href") {
-            Some(href) => href,
-            None => continue,
+    for entry in entries {
+        let Some(file_url) = urls::resolve(base, &entry.href) else {
+            continue;
         };
 
-        if href == "../" || href == ".." || href == "/" || href.starts_with("?") {
+        let name = entry.name.as_str();
+        let file_type = classify(
+            &options.file_types,
+            name,
+            entry.is_directory,
+            entry.content_type.as_deref(),
+        );
+        let is_directory = file_type == FileType::Directory;
+
+        let key = urls::canonicalize(&file_url, is_directory);
+        if !context.visited.insert(&file_url, is_directory) {
+            listed.push((key, None));
             continue;
         }
 
-        let clean_href = href.trim_end_matches('/');
-        let file_url = if href.starts_with("http") {
-            href.to_string()
+        if !context.scope.contains(&file_url) {
+            external_links.push(file_url);
+            continue;
+        }
+
+        if !backend.allows(&file_url) {
+            robots_excluded.push(file_url);
+            continue;
+        }
+
+        let path = urls::decoded_path(&file_url);
+        let allowed = if is_directory {
+            options.filters.allows_directory(&path)
         } else {
-            let base_url = if url.ends_with('/') {
-                url.to_string()
-            } else {
-                format!("{}/", url)
-            };
-            let path_part = href.trim_start_matches('/');
-            format!("{}{}", base_url, path_part)
+            options.filters.allows_file(&path, name)
+                && entry
+                    .size
+                    .is_none_or(|size| options.filters.allows_size(size))
+        };
+        if !allowed {
+            filtered += 1;
+            continue;
+        }
+
+        // Files are probed for a missing size, and entries whose name leaves
+        // the type open for their MIME type. Media files need their size for
+        // the bitrate, and for the duration of constant-bitrate MP3s.
+        let needs_size = !is_directory && entry.size.is_none();
+        let needs_type =
+            !entry.is_directory && entry.content_type.is_none() && is_type_unclear(&file_type);
+        let wants_probe = (options.probe_sizes && (needs_size || needs_type))
+            || (options.media_metadata && needs_size && is_media(&file_type));
+        let probe = match wants_probe {
+            true => backend.probe(&file_url).unwrap_or_else(|e| {
+                eprintln!("Failed to probe {}: {}", file_url, e);
+                Probe::default()
+            }),
+            false => Probe::default(),
         };
+        let content_type = entry.content_type.or(probe.content_type);
+        let file_type = classify(
+            &options.file_types,
+            name,
+            entry.is_directory,
+            content_type.as_deref(),
+        );
 
-        let name = clean_href;
-        let is_directory = href.ends_with('/');
+        let detected_type = match options.sniff_types && !entry.is_directory {
+            true => sniff_type(&file_url, &file_type, backend),
+            false => None,
+        };
+        let type_mismatch = file_type != FileType::Directory
+            && detected_type.as_ref().is_some_and(|detected| {
+                !sniff::is_consistent(&file_type, detected, &options.file_types)
+            });
+        // An extensionless entry recognized by its MIME type or content is
+        // a file after all.
+        let is_file = file_type != FileType::Directory || detected_type.is_some();
+        if is_directory && is_file && !options.filters.allows_file(&path, name) {
+            filtered += 1;
+            continue;
+        }
+        let is_directory = !is_file;
 
-        // Size extraction is server-specific; fallback to 0
-        let size = 0;
+        let (size, size_source) = match (entry.size, probe.size) {
+            (Some(size), _) => (size, SizeSource::Listing),
+            (None, Some(size)) if !is_directory => (size, SizeSource::Header),
+            _ => (0, SizeSource::Unknown),
+        };
+        if size_source == SizeSource::Header && !options.filters.allows_size(size) {
+            filtered += 1;
+            continue;
+        }
 
-        let file_type = if is_directory {
-            FileType::Directory
-        } else {
-            get_file_type(name)
+        let mut file_info = FileInfo {
+            name: name.to_string(),
+            url: file_url,
+            size,
+            size_source,
+            modified: entry.modified,
+            content_type,
+            etag: entry.etag,
+            file_type,
+            detected_type,
+            type_mismatch,
+            archive_entries: None,
+            image: None,
+            media: None,
+            document: None,
+            nsfw_unchecked: false,
         };
 
-        if matches!(file_type, FileType::Image(_)) {
-            let image_url = file_url.trim_end_matches('/').to_string();
-            match nsfw_detector.is_nsfw(&image_url, client) {
-                Ok(nsfw) => {
-                    if nsfw {
-                        report.nsfw_count += 1;
-                        report.nsfw_files.push(image_url.clone());
-                    }
+        if options.list_archives && is_zip(&file_info.name, file_info.effective_type()) {
+            match archive::list_zip(&file_info.url, backend) {
+                Ok(archive_entries) => {
+                    println!(
+                        "Listed {} entries in archive {}",
+                        archive_entries.len(),
+                        file_info.url
+                    );
+                    file_info.archive_entries = Some(archive_entries);
                 }
-                Err(e) => {
-                    eprintln!("Failed to check NSFW for {}: {}", image_url, e);
+                Err(e) => eprintln!("Failed to list archive {}: {}", file_info.url, e),
+            }
+        }
+
+        if options.media_metadata && is_media(file_info.effective_type()) {
+            let size = (file_info.size_source != SizeSource::Unknown).then_some(file_info.size);
+            match media::read(&file_info.url, size, backend) {
+                Ok(metadata) => file_info.media = metadata,
+                Err(e) => eprintln!("Failed to read media metadata for {}: {}", file_info.url, e),
+            }
+        }
+
+        if options.document_metadata
+            && document::is_supported(&file_info.name, file_info.effective_type())
+        {
+            match document::read(
+                &file_info.url,
+                &file_info.name,
+                file_info.effective_type(),
+                backend,
+            ) {
+                Ok(metadata) => file_info.document = metadata,
+                Err(e) => eprintln!(
+                    "Failed to read document metadata for {}: {}",
+                    file_info.url, e
+                ),
+            }
+        }
+
+        let too_large =
+            file_info.size_source != SizeSource::Unknown && file_info.size > MAX_NSFW_IMAGE;
+        if file_info.effective_type().is(filetypes::IMAGE) && too_large {
+            let image_url = file_info.url.trim_end_matches('/').to_string();
+            file_info.nsfw_unchecked = true;
+            println!(
+                "Skipping NSFW check for {}: larger than {} MB",
+                image_url,
+                MAX_NSFW_IMAGE / 1024 / 1024
+            );
+            match imagemeta::fetch(&image_url, backend) {
+                Ok(metadata) => file_info.image = Some(metadata),
+                Err(e) => eprintln!("Failed to read image metadata for {}: {}", image_url, e),
+            }
+        } else if file_info.effective_type().is(filetypes::IMAGE) {
+            let image_url = file_info.url.trim_end_matches('/').to_string();
+            let image = backend.fetch(&image_url);
+            // The download made for the NSFW check also yields the metadata.
+            if let Ok(data) = &image {
+                file_info.image = Some(imagemeta::read(data));
+            }
+            if is_nsfw_image(&image_url, image, context.nsfw_detector) {
+                nsfw_files.insert(image_url);
+            }
+        }
+        if options.check_archive_images {
+            let images = file_info
+                .archive_entries
+                .iter()
+                .flatten()
+                .filter(|e| options.file_types.classify(&e.name).is(filetypes::IMAGE));
+            for archive_entry in images {
+                let image_url = archive::entry_url(&file_info.url, &archive_entry.name);
+                let image = archive::read_entry(&file_info.url, archive_entry, backend);
+                if is_nsfw_image(&image_url, image, context.nsfw_detector) {
+                    nsfw_files.insert(image_url);
                 }
             }
         }
 
-        let file_info = FileInfo {
-            name: name.to_string(),
-            url: file_url.clone(),
-            size,
-            file_type: file_type.clone(),
+        listed.push((key, Some(files.len())));
+        files.push(file_info);
+    }
+
+    DirectoryListing {
+        files,
+        entries: listed,
+        nsfw_files,
+        external_links,
+        filtered,
+        robots_excluded,
+        fingerprint: 0,
+    }
+}
+
+/// Crawls `url` up to `options.max_depth` using the concurrent crawler and
+/// aggregates the listings into a report. Results are assembled in listing
+/// order, so the output does not depend on the order pages were fetched in.
+/// A checkpoint passed as `resume` continues an earlier, unfinished scan.
+pub fn scan_directory(
+    url: &str,
+    options: &ScanOptions,
+    backend: &dyn Backend,
+    nsfw_detector: &NSFWDetector,
+    resume: Option<Checkpoint>,
+) -> Result<(DirectoryReport, Vec<FileInfo>), Box<dyn Error>> {
+    let context = ScanContext {
+        options,
+        backend,
+        nsfw_detector,
+        scope: Scope::new(url, !options.allow_parent, &options.allowed_hosts),
+        visited: VisitedSet::new(),
+    };
+    if !backend.allows(url) {
+        return Err(format!("{} is disallowed by robots.txt", url).into());
+    }
+    let results = crawler::crawl(url, &context, resume);
+    let (mut report, files) = aggregate(url, &results.listings)?;
+    // Links out of scope are recorded by whichever listing found them first.
+    report.external_references.sort();
+    report.robots_excluded.sort();
+    report.duplicates_suppressed = results.duplicates_suppressed;
+    report.loops_suppressed = results.loops_suppressed;
+    report.incomplete = !results.unvisited.is_empty();
+    report.unvisited_directories = results.unvisited;
+    Ok((report, files))
+}
+
+/// Builds the report for `url` from the collected listings, recursing into
+/// subdirectories in listing order. Directories that have not been listed
+/// (yet) contribute nothing; those whose fetch failed are recorded in
+/// `failed_directories`.
+///
+/// An entry linked from several directories is reported under the first of
+/// them in this order, not under the one that happened to list it first
+/// during the crawl, so the report does not depend on fetch timing.
+pub fn aggregate(
+    url: &str,
+    listings: &Listings,
+) -> Result<(DirectoryReport, Vec<FileInfo>), Box<dyn Error>> {
+    let mut claimed = HashMap::new();
+    for listing in listings
+        .values()
+        .filter_map(|result| result.as_ref().as_ref().ok())
+    {
+        for (key, position) in &listing.entries {
+            if let Some(position) = position {
+                claimed.insert(key.as_str(), (listing, &listing.files[*position]));
+            }
+        }
+    }
+    let root = urls::canonicalize(url, true);
+    let mut reported = HashSet::from([root.as_str()]);
+    aggregate_directory(url, listings, &claimed, &mut reported)
+}
+
+/// Entries by canonical URL, with the listing that claimed them.
+type Claimed<'a> = HashMap<&'a str, (&'a DirectoryListing, &'a FileInfo)>;
+
+fn aggregate_directory<'a>(
+    url: &str,
+    listings: &'a Listings,
+    claimed: &Claimed<'a>,
+    reported: &mut HashSet<&'a str>,
+) -> Result<(DirectoryReport, Vec<FileInfo>), Box<dyn Error>> {
+    let listing = match listings.get(url).map(|result| result.as_ref()) {
+        Some(Ok(listing)) => listing,
+        Some(Err(e)) => return Err(e.clone().into()),
+        None => return Ok((DirectoryReport::new(), vec![])),
+    };
+
+    let mut report = DirectoryReport::new();
+    let mut all_files = Vec::new();
+    report
+        .external_references
+        .extend(listing.external_links.iter().cloned());
+    report.filtered_count += listing.filtered;
+    report
+        .robots_excluded
+        .extend(listing.robots_excluded.iter().cloned());
+
+    for (key, _) in &listing.entries {
+        if !reported.insert(key) {
+            continue;
+        }
+        let Some(&(owner, file_info)) = claimed.get(key.as_str()) else {
+            continue;
         };
+        let file_url = &file_info.url;
+        let size = file_info.size;
 
-        if is_directory {
+        let image_url = file_url.trim_end_matches('/');
+        if owner.nsfw_files.contains(image_url) {
+            report.nsfw_count += 1;
+            report.nsfw_files.push(image_url.to_string());
+        }
+
+        if *file_info.effective_type() == FileType::Directory {
             report.total_directories += 1;
-            if depth < max_depth {
-                match scan_directory(&file_url, depth + 1, max_depth, client, nsfw_detector) {
-                    Ok((subdir_report, subdir_files)) => {
-                        let subdir_size = subdir_report.total_size;
-                        if report.largest_directory.is_none()
-                            || report.largest_directory.as_ref().unwrap().1 < subdir_size
-                        {
-                            report.largest_directory = Some((file_url.clone(), subdir_size));
-                        }
-                        report.total_files += subdir_report.total_files;
-                        report.total_directories += subdir_report.total_directories;
-                        report.total_size += subdir_report.total_size;
-                        report.image_count += subdir_report.image_count;
-                        report.video_count += subdir_report.video_count;
-                        report.audio_count += subdir_report.audio_count;
-                        report.document_count += subdir_report.document_count;
-                        report.other_count += subdir_report.other_count;
-                        report.nsfw_count += subdir_report.nsfw_count;
-                        for (format, count) in subdir_report.files_by_type {
-                            *report.files_by_type.entry(format).or_insert(0) += count;
-                        }
-                        if let Some(subdir_largest_file) = subdir_report.largest_file {
-                            if report.largest_file.is_none()
-                                || report.largest_file.as_ref().unwrap().size
-                                    < subdir_largest_file.size
-                            {
-                                report.largest_file = Some(subdir_largest_file.clone());
-                            }
-                        }
-                        all_files.extend(subdir_files);
+            match aggregate_directory(file_url, listings, claimed, reported) {
+                Ok((subdir_report, subdir_files)) => {
+                    let subdir_size = subdir_report.total_size;
+                    if report.largest_directory.is_none()
+                        || report.largest_directory.as_ref().unwrap().1 < subdir_size
+                    {
+                        report.largest_directory = Some((file_url.clone(), subdir_size));
+                    }
+                    report.total_files += subdir_report.total_files;
+                    report.total_directories += subdir_report.total_directories;
+                    report.total_size += subdir_report.total_size;
+                    report.unknown_size_count += subdir_report.unknown_size_count;
+                    report.nsfw_count += subdir_report.nsfw_count;
+                    report.nsfw_files.extend(subdir_report.nsfw_files);
+                    report.nsfw_unchecked.extend(subdir_report.nsfw_unchecked);
+                    report.filtered_count += subdir_report.filtered_count;
+                    report
+                        .failed_directories
+                        .extend(subdir_report.failed_directories);
+                    report.robots_excluded.extend(subdir_report.robots_excluded);
+                    report.type_mismatches.extend(subdir_report.type_mismatches);
+                    report.archives.extend(subdir_report.archives);
+                    report
+                        .geotagged_images
+                        .extend(subdir_report.geotagged_images);
+                    report
+                        .external_references
+                        .extend(subdir_report.external_references);
+                    for (format, count) in subdir_report.files_by_type {
+                        *report.files_by_type.entry(format).or_insert(0) += count;
                     }
-                    Err(e) => eprintln!("Error scanning subdirectory {}: {}", file_url, e),
+                    for (category, count) in subdir_report.files_by_category {
+                        *report.files_by_category.entry(category).or_insert(0) += count;
+                    }
+                    for (class, count) in subdir_report.images_by_resolution {
+                        *report.images_by_resolution.entry(class).or_insert(0) += count;
+                    }
+                    report.total_media_duration += subdir_report.total_media_duration;
+                    for (codec, count) in subdir_report.media_by_codec {
+                        *report.media_by_codec.entry(codec).or_insert(0) += count;
+                    }
+                    for (author, count) in subdir_report.documents_by_author {
+                        *report.documents_by_author.entry(author).or_insert(0) += count;
+                    }
+                    if let Some(subdir_largest_file) = subdir_report.largest_file
+                        && (report.largest_file.is_none()
+                            || report.largest_file.as_ref().unwrap().size
+                                < subdir_largest_file.size)
+                    {
+                        report.largest_file = Some(subdir_largest_file.clone());
+                    }
+                    all_files.extend(subdir_files);
                 }
+                Err(e) => report
+                    .failed_directories
+                    .push((file_url.clone(), e.to_string())),
             }
         } else {
             report.total_files += 1;
             report.total_size += size;
+            if file_info.size_source == SizeSource::Unknown {
+                report.unknown_size_count += 1;
+            }
             if report.largest_file.is_none() || report.largest_file.as_ref().unwrap().size < size {
                 report.largest_file = Some(file_info.clone());
             }
-            match &file_type {
-                FileType::Image(format) => {
-                    report.image_count += 1;
-                    *report.files_by_type.entry(format.clone()).or_insert(0) += 1;
-                }
-                FileType::Video(format) => {
-                    report.video_count += 1;
-                    *report.files_by_type.entry(format.clone()).or_insert(0) += 1;
+            if file_info.nsfw_unchecked {
+                report.nsfw_unchecked.push(file_url.clone());
+            }
+            if file_info.type_mismatch {
+                report.type_mismatches.push((
+                    file_url.clone(),
+                    format!(
+                        "named as {}, content is {}",
+                        file_info.file_type.label(),
+                        file_info.effective_type().label()
+                    ),
+                ));
+            }
+            if let Some(image) = &file_info.image {
+                if let Some(class) = image.resolution_class() {
+                    *report
+                        .images_by_resolution
+                        .entry(class.to_string())
+                        .or_insert(0) += 1;
                 }
-                FileType::Audio(format) => {
-                    report.audio_count += 1;
-                    *report.files_by_type.entry(format.clone()).or_insert(0) += 1;
+                if let Some(summary) = image.gps_summary() {
+                    report.geotagged_images.push((file_url.clone(), summary));
                 }
-                FileType::Document(format) => {
-                    report.document_count += 1;
-                    *report.files_by_type.entry(format.clone()).or_insert(0) += 1;
+            }
+            if let Some(media) = &file_info.media {
+                report.total_media_duration += media.duration.unwrap_or(0.0);
+                for codec in media.codecs() {
+                    *report.media_by_codec.entry(codec.clone()).or_insert(0) += 1;
                 }
-                FileType::Other(ext) => {
-                    report.other_count += 1;
-                    *report.files_by_type.entry(ext.clone()).or_insert(0) += 1;
+            }
+            if let Some(author) = file_info.document.as_ref().and_then(|d| d.author.clone()) {
+                *report.documents_by_author.entry(author).or_insert(0) += 1;
+            }
+            if let Some(archive_entries) = &file_info.archive_entries {
+                for archive_entry in archive_entries {
+                    let entry_url = archive::entry_url(file_url, &archive_entry.name);
+                    if owner.nsfw_files.contains(&entry_url) {
+                        report.nsfw_count += 1;
+                        report.nsfw_files.push(entry_url);
+                    }
                 }
-                FileType::Directory => {}
+                report
+                    .archives
+                    .push((file_url.clone(), archive_entries.clone()));
+            }
+            if let FileType::File { category, format } = file_info.effective_type() {
+                *report.files_by_type.entry(format.clone()).or_insert(0) += 1;
+                *report
+                    .files_by_category
+                    .entry(category.clone())
+                    .or_insert(0) += 1;
             }
         }
 
-        all_files.push(file_info);
+        all_files.push(file_info.clone());
     }
 
     Ok((report, all_files))
@@ -0,0 +1,144 @@
+use crate::urls::host_key;
+use url::Url;
+
+/// Decides which links the crawler may follow. By default only URLs on the
+/// starting host and below the starting path are in scope; additional hosts
+/// can be allowed explicitly and are then followed on any path.
+pub struct Scope {
+    host: Option<String>,
+    path_prefix: String,
+    restrict_path: bool,
+    allowed_hosts: Vec<AllowedHost>,
+}
+
+/// A host given with `--allow-host`. Without a port it is allowed on every
+/// port; with one, only on that port.
+struct AllowedHost {
+    name: String,
+    port: Option<u16>,
+}
+
+impl AllowedHost {
+    fn parse(host: &str) -> Self {
+        let host = host.to_ascii_lowercase();
+        // The port follows the last colon, unless that colon is inside an
+        // IPv6 address.
+        let split = host
+            .rsplit_once(':')
+            .filter(|(name, _)| !name.contains(':') || name.ends_with(']'))
+            .and_then(|(name, port)| Some((name.to_string(), port.parse().ok()?)));
+        match split {
+            Some((name, port)) => AllowedHost {
+                name,
+                port: Some(port),
+            },
+            None => AllowedHost {
+                name: host,
+                port: None,
+            },
+        }
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        url.host_str()
+            .is_some_and(|name| name.eq_ignore_ascii_case(&self.name))
+            && self
+                .port
+                .is_none_or(|port| url.port_or_known_default() == Some(port))
+    }
+}
+
+impl Scope {
+    pub fn new(root: &str, restrict_path: bool, allowed_hosts: &[String]) -> Self {
+        let parsed = Url::parse(root).ok();
+        let host = parsed.as_ref().and_then(host_key);
+        let path_prefix = parsed
+            .as_ref()
+            .map(|u| {
+                // The root is a directory even when given without the
+                // trailing slash.
+                let path = u.path();
+                match path.ends_with('/') {
+                    true => path.to_string(),
+                    false => format!("{}/", path),
+                }
+            })
+            .unwrap_or_else(|| "/".to_string());
+
+        Scope {
+            host,
+            path_prefix,
+            restrict_path,
+            allowed_hosts: allowed_hosts
+                .iter()
+                .map(|host| AllowedHost::parse(host))
+                .collect(),
+        }
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        let Ok(parsed) = Url::parse(url) else {
+            return false;
+        };
+        if self.allowed_hosts.iter().any(|host| host.matches(&parsed)) {
+            return true;
+        }
+        // Local `file://` URLs have no host and match a local root.
+        if host_key(&parsed) != self.host {
+            return false;
+        }
+        !self.restrict_path || parsed.path().starts_with(&self.path_prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stays_below_root_path() {
+        let scope = Scope::new("http://example.com/pub/", true, &[]);
+        assert!(scope.contains("http://example.com/pub/linux/"));
+        assert!(!scope.contains("http://example.com/private/"));
+        assert!(!scope.contains("http://example.com:8080/pub/"));
+        assert!(!scope.contains("http://other.example.com/pub/"));
+    }
+
+    #[test]
+    fn treats_root_without_slash_as_directory() {
+        let scope = Scope::new("http://example.com/pub", true, &[]);
+        assert!(scope.contains("http://example.com/pub/file.iso"));
+        assert!(!scope.contains("http://example.com/public/"));
+        assert!(!scope.contains("http://example.com/other/"));
+    }
+
+    #[test]
+    fn allows_parent_on_same_host() {
+        let scope = Scope::new("http://example.com/pub/", false, &[]);
+        assert!(scope.contains("http://example.com/private/"));
+        assert!(!scope.contains("http://other.example.com/"));
+    }
+
+    #[test]
+    fn allows_hosts_on_any_or_given_port() {
+        let allowed = [
+            "Mirror.example.com".to_string(),
+            "cdn.example.com:8080".to_string(),
+            "[::1]:8443".to_string(),
+        ];
+        let scope = Scope::new("http://example.com/pub/", true, &allowed);
+        assert!(scope.contains("http://mirror.example.com/any/path"));
+        assert!(scope.contains("https://mirror.example.com:8080/"));
+        assert!(scope.contains("http://cdn.example.com:8080/"));
+        assert!(!scope.contains("http://cdn.example.com/"));
+        assert!(scope.contains("https://[::1]:8443/"));
+        assert!(!scope.contains("https://[::1]/"));
+    }
+
+    #[test]
+    fn matches_local_root() {
+        let scope = Scope::new("file:///srv/files/", true, &[]);
+        assert!(scope.contains("file:///srv/files/a/b.txt"));
+        assert!(!scope.contains("file:///etc/passwd"));
+    }
+}
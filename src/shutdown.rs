@@ -0,0 +1,41 @@
+use std::error::Error;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs a SIGINT/SIGTERM handler. The first signal asks the crawler to
+/// stop taking new directories so the partial report can be written; a
+/// second one exits immediately.
+pub fn install_handler() -> Result<(), Box<dyn Error>> {
+    ctrlc::set_handler(|| {
+        if STOP_REQUESTED.swap(true, Ordering::SeqCst) {
+            eprintln!("\nForced exit, partial results discarded");
+            process::exit(130);
+        }
+        eprintln!("\nInterrupted, finishing in-flight requests (press Ctrl-C again to abort)...");
+    })?;
+    Ok(())
+}
+
+pub fn requested() -> bool {
+    STOP_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sleeps for `duration` in short slices, returning early with `false` once
+/// a stop is requested.
+pub fn sleep(duration: Duration) -> bool {
+    let end = Instant::now() + duration;
+    loop {
+        if requested() {
+            return false;
+        }
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(Duration::from_millis(100)));
+    }
+}
@@ -0,0 +1,416 @@
+use crate::filetypes::{ARCHIVE, AUDIO, DOCUMENT, EXECUTABLE, FileTypes, IMAGE, OTHER, VIDEO};
+use crate::report::FileType;
+
+/// Number of bytes fetched from the start of a file. Enough for every
+/// signature below and for telling text from binary data.
+pub const SNIFF_LENGTH: usize = 4096;
+
+/// MIME subtypes and detected formats that name the same format.
+const ALIASES: &[(&str, &str)] = &[
+    ("gzip", "gz"),
+    ("bzip2", "bz2"),
+    ("opus", "ogg"),
+    ("quicktime", "mp4"),
+    ("m4v", "mp4"),
+    ("webm", "matroska"),
+    ("svg+xml", "svg"),
+    ("wave", "wav"),
+    ("vnd.wave", "wav"),
+    ("msvideo", "avi"),
+    ("flv", "flash"),
+    ("ms-wmv", "windows media"),
+    ("ms-asf", "windows media"),
+    ("7z-compressed", "7z"),
+    ("vnd.rar", "rar"),
+    ("rar-compressed", "rar"),
+    ("msdownload", "exe"),
+    ("ms-msi", "office"),
+];
+
+/// MIME types whose subtype alone is ambiguous: `video/mpeg` is MPEG
+/// video, but `audio/mpeg` is MP3.
+const MIME_ALIASES: &[(&str, &str)] = &[("audio/mpeg", "mp3")];
+
+/// Categories whose files are never plain text.
+const BINARY_CATEGORIES: &[&str] = &[IMAGE, VIDEO, AUDIO, ARCHIVE, EXECUTABLE];
+
+/// Extensions of the files that may start with a detected format: its own,
+/// and those of formats stored in it. A name is checked against them
+/// through the configured taxonomy, whatever its formats are called.
+const CARRIERS: &[(&str, &[&str])] = &[
+    ("jpeg", &["jpg", "jpeg"]),
+    ("png", &["png"]),
+    ("gif", &["gif"]),
+    ("webp", &["webp"]),
+    ("bmp", &["bmp"]),
+    ("tiff", &["tif", "tiff"]),
+    ("heic", &["heic", "heif"]),
+    ("mp4", &["mp4", "m4v", "mov"]),
+    ("m4a", &["m4a", "m4b"]),
+    ("matroska", &["mkv", "mka", "webm"]),
+    ("avi", &["avi"]),
+    ("flash", &["flv"]),
+    ("windows media", &["wmv", "wma", "asf"]),
+    ("mp3", &["mp3"]),
+    ("wav", &["wav"]),
+    ("ogg", &["ogg", "oga", "ogv", "opus"]),
+    ("flac", &["flac"]),
+    ("aac", &["aac"]),
+    ("pdf", &["pdf"]),
+    ("office", &["doc", "xls", "ppt", "msi"]),
+    (
+        "zip",
+        &[
+            "zip", "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk", "cbz", "xps",
+        ],
+    ),
+    ("gz", &["gz", "tgz", "tar.gz"]),
+    ("7z", &["7z"]),
+    ("rar", &["rar", "cbr"]),
+    ("xz", &["xz", "txz", "tar.xz"]),
+    ("bz2", &["bz2", "tbz2", "tar.bz2"]),
+    ("exe", &["exe", "dll"]),
+    ("elf", &["appimage"]),
+    (
+        "html",
+        &["html", "htm", "xhtml", "shtml", "php", "asp", "aspx", "jsp"],
+    ),
+    ("svg", &["svg"]),
+    ("xml", &["xml", "svg"]),
+];
+
+/// Detected formats that are markup, which plain text may hold as well.
+const MARKUP: &[&str] = &["html", "svg", "xml"];
+
+fn carriers(detected_format: &str) -> &'static [&'static str] {
+    CARRIERS
+        .iter()
+        .find(|(format, _)| *format == detected_format)
+        .map_or(&[], |(_, extensions)| extensions)
+}
+
+/// Whether `file_types` gives a file with one of `extensions` the type
+/// `claimed`.
+fn named_by(file_types: &FileTypes, claimed: &FileType, extensions: &[&str]) -> bool {
+    extensions
+        .iter()
+        .any(|extension| file_types.classify(&format!("file.{}", extension)) == *claimed)
+}
+
+fn lower_format(file_type: &FileType) -> Option<String> {
+    let format = file_type.format()?.to_ascii_lowercase();
+    if let Some((_, canonical)) = MIME_ALIASES.iter().find(|(mime, _)| *mime == format) {
+        return Some(canonical.to_string());
+    }
+    // MIME types from the server, e.g. `image/png` or `audio/x-flac`.
+    let format = match format.split_once('/') {
+        Some((_, subtype)) => subtype.trim_start_matches("x-").to_string(),
+        None => format,
+    };
+    Some(
+        ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == format)
+            .map_or(format, |(_, canonical)| canonical.to_string()),
+    )
+}
+
+/// Whether `data` looks like text: valid UTF-8 (allowing a character cut
+/// off at the end) without control characters other than whitespace.
+fn is_text(data: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(e) if data.len() - e.valid_up_to() < 4 && e.error_len().is_none() => {
+            std::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or("")
+        }
+        Err(_) => return false,
+    };
+    !valid.is_empty()
+        && valid
+            .chars()
+            .all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+}
+
+/// Identifies a file from its first bytes. Returns `None` for formats
+/// without a known signature.
+pub fn detect(data: &[u8]) -> Option<FileType> {
+    let starts = |signature: &[u8]| data.starts_with(signature);
+    let at = |offset: usize, signature: &[u8]| {
+        data.get(offset..offset + signature.len()) == Some(signature)
+    };
+    let image = |f: &str| Some(FileType::file(IMAGE, f));
+    let video = |f: &str| Some(FileType::file(VIDEO, f));
+    let audio = |f: &str| Some(FileType::file(AUDIO, f));
+    let document = |f: &str| Some(FileType::file(DOCUMENT, f));
+    let archive = |f: &str| Some(FileType::file(ARCHIVE, f));
+    let executable = |f: &str| Some(FileType::file(EXECUTABLE, f));
+    let other = |f: &str| Some(FileType::file(OTHER, f));
+
+    if starts(b"\xFF\xD8\xFF") {
+        return image("JPEG");
+    }
+    if starts(b"\x89PNG\r\n\x1a\n") {
+        return image("PNG");
+    }
+    if starts(b"GIF87a") || starts(b"GIF89a") {
+        return image("GIF");
+    }
+    if starts(b"RIFF") && at(8, b"WEBP") {
+        return image("WebP");
+    }
+    if starts(b"BM") && data.len() > 14 && at(6, b"\0\0\0\0") {
+        return image("BMP");
+    }
+    if starts(b"II*\0") || starts(b"MM\0*") {
+        return image("TIFF");
+    }
+    if at(4, b"ftyp") {
+        return match data.get(8..12) {
+            Some(b"qt  ") => video("QuickTime"),
+            Some(b"M4A ") | Some(b"M4B ") => audio("M4A"),
+            Some(b"heic") | Some(b"heix") | Some(b"mif1") => image("HEIC"),
+            _ => video("MP4"),
+        };
+    }
+    if starts(b"\x1A\x45\xDF\xA3") {
+        let header = &data[..data.len().min(64)];
+        return match header.windows(4).any(|w| w == b"webm") {
+            true => video("WebM"),
+            false => video("Matroska"),
+        };
+    }
+    if starts(b"RIFF") && at(8, b"AVI ") {
+        return video("AVI");
+    }
+    if starts(b"FLV\x01") {
+        return video("Flash");
+    }
+    if starts(b"\x30\x26\xB2\x75\x8E\x66\xCF\x11") {
+        return video("Windows Media");
+    }
+    // MPEG audio frame sync (11 set bits) with layer III.
+    if starts(b"ID3") || (data.len() > 1 && data[0] == 0xFF && data[1] & 0xE6 == 0xE2) {
+        return audio("MP3");
+    }
+    if starts(b"RIFF") && at(8, b"WAVE") {
+        return audio("WAV");
+    }
+    if starts(b"OggS") {
+        return audio("OGG");
+    }
+    if starts(b"fLaC") {
+        return audio("FLAC");
+    }
+    // ADTS frame sync with layer 0.
+    if data.len() > 1 && data[0] == 0xFF && data[1] & 0xF6 == 0xF0 {
+        return audio("AAC");
+    }
+    if starts(b"%PDF-") {
+        return document("PDF");
+    }
+    // Legacy Word, Excel and PowerPoint files share the OLE container.
+    if starts(b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1") {
+        return document("Office");
+    }
+    if starts(b"PK\x03\x04") || starts(b"PK\x05\x06") {
+        return archive("ZIP");
+    }
+    if starts(b"\x1F\x8B") {
+        return archive("GZIP");
+    }
+    if starts(b"7z\xBC\xAF\x27\x1C") {
+        return archive("7z");
+    }
+    if starts(b"Rar!\x1A\x07") {
+        return archive("RAR");
+    }
+    if starts(b"\xFD7zXZ\0") {
+        return archive("XZ");
+    }
+    if starts(b"BZh") {
+        return archive("BZIP2");
+    }
+    if starts(b"MZ") {
+        return executable("EXE");
+    }
+    if starts(b"\x7FELF") {
+        return executable("ELF");
+    }
+
+    if !is_text(data) {
+        return None;
+    }
+    let text = String::from_utf8_lossy(data);
+    let start = text
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .to_ascii_lowercase();
+    if start.starts_with("<!doctype html")
+        || start.starts_with("<html")
+        || start.starts_with("<head")
+    {
+        return other("html");
+    }
+    if (start.starts_with("<?xml") || start.starts_with("<svg")) && start.contains("<svg") {
+        return image("SVG");
+    }
+    if start.starts_with("<?xml") {
+        return other("xml");
+    }
+    document("Text")
+}
+
+/// Whether a detected type is generic markup or text, which does not
+/// prove that an extensionless entry is a file rather than a directory
+/// page.
+pub fn is_markup_or_text(detected: &FileType) -> bool {
+    matches!(
+        lower_format(detected).as_deref(),
+        Some("html" | "xml" | "text")
+    )
+}
+
+/// Whether the content found agrees with the type the file name claims.
+/// Names are compared through `file_types`, and content types from the
+/// server by their MIME type.
+pub fn is_consistent(claimed: &FileType, detected: &FileType, file_types: &FileTypes) -> bool {
+    let (Some(claimed_format), Some(detected_format)) =
+        (lower_format(claimed), lower_format(detected))
+    else {
+        return true;
+    };
+    if claimed_format == detected_format
+        || named_by(file_types, claimed, carriers(&detected_format))
+    {
+        return true;
+    }
+
+    let mime = claimed.format().is_some_and(|format| format.contains('/'));
+    // .docx, .xlsx and .pptx are ZIP archives too.
+    let office = mime
+        && (claimed_format == "msword"
+            || claimed_format.starts_with("vnd.ms-")
+            || claimed_format.starts_with("vnd.openxmlformats-officedocument")
+            || claimed_format.starts_with("vnd.oasis.opendocument"));
+    // Text is plausible for anything but binary media, archives, programs
+    // and binary documents.
+    let binary = BINARY_CATEGORIES
+        .iter()
+        .any(|category| claimed.is(category))
+        || office
+        || (mime && claimed_format == "pdf")
+        || CARRIERS
+            .iter()
+            .filter(|(format, _)| !MARKUP.contains(format))
+            .any(|(_, extensions)| named_by(file_types, claimed, extensions));
+    match detected_format.as_str() {
+        "zip" | "office" => office,
+        "text" | "xml" => !binary,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_signatures() {
+        let cases: &[(&[u8], &str, &str)] = &[
+            (b"\xFF\xD8\xFF\xE0\0\x10JFIF", IMAGE, "JPEG"),
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", IMAGE, "PNG"),
+            (b"\0\0\0\x18ftypisom\0\0\0\0", VIDEO, "MP4"),
+            (b"\0\0\0\x18ftypM4A \0\0\0\0", AUDIO, "M4A"),
+            (b"\x1A\x45\xDF\xA3\x9F\x42\x82\x84webm", VIDEO, "WebM"),
+            (b"ID3\x04\0\0\0\0\0\0", AUDIO, "MP3"),
+            (b"\xFF\xFB\x90\x64", AUDIO, "MP3"),
+            (b"\xFF\xF1\x50\x80", AUDIO, "AAC"),
+            (b"%PDF-1.7\n", DOCUMENT, "PDF"),
+            (b"PK\x03\x04\x14\0", ARCHIVE, "ZIP"),
+            (b"Rar!\x1A\x07\x01\0", ARCHIVE, "RAR"),
+            (b"\x7FELF\x02\x01\x01", EXECUTABLE, "ELF"),
+            (b"<!DOCTYPE html><html>", OTHER, "html"),
+            (b"<?xml version=\"1.0\"?><svg>", IMAGE, "SVG"),
+            (b"plain notes\n", DOCUMENT, "Text"),
+        ];
+        for (data, category, format) in cases {
+            assert_eq!(
+                detect(data),
+                Some(FileType::file(category, format)),
+                "{:?}",
+                data
+            );
+        }
+        assert_eq!(detect(b"\0\x01\x02\x03binary"), None);
+    }
+
+    #[test]
+    fn accepts_formats_stored_in_others() {
+        let consistent = [
+            (FileType::file(EXECUTABLE, "AppImage"), "ELF", EXECUTABLE),
+            (FileType::file("Ebook", "CBR"), "RAR", ARCHIVE),
+            (FileType::file("Ebook", "CBZ"), "ZIP", ARCHIVE),
+            (FileType::file(DOCUMENT, "Word"), "ZIP", ARCHIVE),
+            (FileType::file(DOCUMENT, "Word"), "Office", DOCUMENT),
+            (FileType::file(ARCHIVE, "TAR.GZ"), "GZIP", ARCHIVE),
+            (FileType::file(AUDIO, "Opus"), "OGG", AUDIO),
+            (FileType::file(AUDIO, "audio/mpeg"), "MP3", AUDIO),
+            (FileType::file(DOCUMENT, "CSV"), "Text", DOCUMENT),
+        ];
+        for (claimed, format, category) in consistent {
+            let detected = FileType::file(category, format);
+            assert!(
+                is_consistent(&claimed, &detected, &FileTypes::default()),
+                "{:?}",
+                claimed
+            );
+        }
+    }
+
+    #[test]
+    fn follows_configured_formats() {
+        let config: toml::Table = toml::from_str(
+            "[file_types.Document]\n\
+             DOCX = [\"docx\"]\n\
+             Notes = [\"txt\"]\n\
+             [file_types.Comics]\n\
+             \"Comic Book\" = [\"cbz\", \"cbr\"]\n",
+        )
+        .unwrap();
+        let file_types = FileTypes::from_config(&config).unwrap();
+        let zip = FileType::file(ARCHIVE, "ZIP");
+        let text = FileType::file(DOCUMENT, "Text");
+        let docx = file_types.classify("report.docx");
+        assert!(is_consistent(&docx, &zip, &file_types));
+        assert!(!is_consistent(&docx, &text, &file_types));
+        let comic = file_types.classify("issue.cbr");
+        assert!(is_consistent(
+            &comic,
+            &FileType::file(ARCHIVE, "RAR"),
+            &file_types
+        ));
+        assert!(!is_consistent(&comic, &text, &file_types));
+        let notes = file_types.classify("todo.txt");
+        assert!(is_consistent(&notes, &text, &file_types));
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        let mismatched = [
+            (FileType::file(EXECUTABLE, "EXE"), "ELF", EXECUTABLE),
+            (FileType::file("Ebook", "CBZ"), "RAR", ARCHIVE),
+            (FileType::file(VIDEO, "MPEG"), "MP3", AUDIO),
+            (FileType::file(VIDEO, "video/mpeg"), "MP3", AUDIO),
+            (FileType::file(IMAGE, "JPEG"), "html", OTHER),
+            (FileType::file(DOCUMENT, "PDF"), "Text", DOCUMENT),
+        ];
+        for (claimed, format, category) in mismatched {
+            let detected = FileType::file(category, format);
+            assert!(
+                !is_consistent(&claimed, &detected, &FileTypes::default()),
+                "{:?}",
+                claimed
+            );
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::http::HttpClient;
+use crate::listing::{self, ListingEntry};
+use crate::urls;
+use reqwest::Method;
+use reqwest::header::CONTENT_TYPE;
+use roxmltree::{Document, Node};
+use std::error::Error;
+
+const DAV_NAMESPACE: &str = "DAV:";
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:">
+  <prop>
+    <resourcetype/>
+    <getcontentlength/>
+    <getlastmodified/>
+    <getcontenttype/>
+    <getetag/>
+  </prop>
+</propfind>"#;
+
+/// Whether the server advertises WebDAV class 1 in the `DAV` header of an
+/// `OPTIONS` response for `url`.
+pub fn supports_dav(url: &str, client: &HttpClient) -> bool {
+    let Ok(response) = client.execute(|c| c.request(Method::OPTIONS, url)) else {
+        return false;
+    };
+    response
+        .headers()
+        .get_all("DAV")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|class| class.trim() == "1")
+}
+
+/// Lists a collection with `PROPFIND Depth: 1`. Returns `None` if the
+/// server does not answer with a multistatus response, so the caller can
+/// fall back to scraping the HTML index.
+pub fn list(url: &str, client: &HttpClient) -> Result<Option<Vec<ListingEntry>>, Box<dyn Error>> {
+    let propfind = Method::from_bytes(b"PROPFIND")?;
+    let response = client.execute(|c| {
+        c.request(propfind.clone(), url)
+            .header("Depth", "1")
+            .header(CONTENT_TYPE, "application/xml; charset=utf-8")
+            .body(PROPFIND_BODY)
+    })?;
+    if response.status().as_u16() != 207 {
+        return Ok(None);
+    }
+
+    let body = response.text()?;
+    Ok(Some(parse_multistatus(url, &body)?))
+}
+
+fn dav_child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.tag_name().namespace() == Some(DAV_NAMESPACE) && n.tag_name().name() == name)
+}
+
+fn dav_text(node: Node, name: &str) -> Option<String> {
+    dav_child(node, name)
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+/// Converts a `<multistatus>` document into listing entries, skipping the
+/// collection itself and properties the server could not return.
+fn parse_multistatus(url: &str, body: &str) -> Result<Vec<ListingEntry>, Box<dyn Error>> {
+    let document = Document::parse(body)?;
+    let own_url = urls::canonicalize(url, true);
+    let mut entries = Vec::new();
+
+    for response in document
+        .descendants()
+        .filter(|n| n.tag_name().namespace() == Some(DAV_NAMESPACE))
+        .filter(|n| n.tag_name().name() == "response")
+    {
+        let Some(href) = dav_text(response, "href") else {
+            continue;
+        };
+        let Some(resolved) = urls::resolve(url, &href) else {
+            continue;
+        };
+
+        // Only the properties of propstat blocks with a 2xx status are used.
+        let props: Vec<Node> = response
+            .children()
+            .filter(|n| n.tag_name().name() == "propstat")
+            .filter(|propstat| {
+                // e.g. `HTTP/1.1 200 OK`
+                dav_text(*propstat, "status").is_none_or(|status| {
+                    status
+                        .split_whitespace()
+                        .nth(1)
+                        .is_some_and(|code| code.starts_with('2'))
+                })
+            })
+            .filter_map(|propstat| dav_child(propstat, "prop"))
+            .collect();
+        let prop = |name: &str| props.iter().find_map(|p| dav_text(*p, name));
+        let is_directory = props.iter().any(|p| {
+            dav_child(*p, "resourcetype").is_some_and(|r| dav_child(r, "collection").is_some())
+        });
+
+        if is_directory && urls::canonicalize(&resolved, true) == own_url {
+            continue;
+        }
+
+        let mut href = href;
+        if is_directory && !href.ends_with('/') {
+            href.push('/');
+        }
+        entries.push(ListingEntry {
+            name: listing::name_from_href(&href),
+            href,
+            size: prop("getcontentlength").and_then(|s| s.parse().ok()),
+            modified: prop("getlastmodified"),
+            content_type: prop("getcontenttype"),
+            etag: prop("getetag"),
+            is_directory,
+        });
+    }
+    Ok(entries)
+}